@@ -0,0 +1,4 @@
+ALTER TABLE visited ADD COLUMN depth INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE visited ADD COLUMN domain TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_visited_domain ON visited(domain);
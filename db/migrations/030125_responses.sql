@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS responses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    final_url TEXT,
+    status_code INTEGER,
+    content_type TEXT,
+    content_length INTEGER,
+    headers TEXT,
+    ttfb_ms INTEGER,
+    duration_ms INTEGER,
+    error TEXT,
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_responses_url ON responses(url);
+CREATE INDEX IF NOT EXISTS idx_responses_status_code ON responses(status_code);
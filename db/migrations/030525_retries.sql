@@ -0,0 +1,3 @@
+ALTER TABLE frontier ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE frontier ADD COLUMN last_error TEXT;
+ALTER TABLE responses ADD COLUMN attempt INTEGER NOT NULL DEFAULT 1;
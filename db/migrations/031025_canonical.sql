@@ -0,0 +1,2 @@
+ALTER TABLE visited ADD COLUMN canonical_url TEXT;
+CREATE INDEX IF NOT EXISTS idx_visited_canonical_url ON visited(canonical_url);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS rejected_urls (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    referrer TEXT,
+    reason TEXT NOT NULL,
+    detail TEXT,
+    rejected_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_rejected_urls_url ON rejected_urls(url);
+CREATE INDEX IF NOT EXISTS idx_rejected_urls_reason ON rejected_urls(reason);
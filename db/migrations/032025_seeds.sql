@@ -0,0 +1,3 @@
+ALTER TABLE frontier ADD COLUMN seed TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_frontier_seed ON frontier(seed);
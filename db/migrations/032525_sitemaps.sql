@@ -0,0 +1,14 @@
+ALTER TABLE frontier ADD COLUMN lastmod TEXT;
+ALTER TABLE frontier ADD COLUMN sitemap_priority REAL;
+
+CREATE TABLE IF NOT EXISTS sitemaps (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    host TEXT,
+    kind TEXT NOT NULL,
+    url_count INTEGER NOT NULL DEFAULT 0,
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(url)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sitemaps_host ON sitemaps(host);
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS feeds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    host TEXT,
+    kind TEXT NOT NULL,
+    title TEXT,
+    item_count INTEGER NOT NULL DEFAULT 0,
+    found_on TEXT,
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(url)
+);
+
+CREATE TABLE IF NOT EXISTS feed_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    feed_url TEXT NOT NULL,
+    url TEXT NOT NULL,
+    title TEXT,
+    published TEXT,
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(feed_url, url)
+);
+
+CREATE INDEX IF NOT EXISTS idx_feeds_host ON feeds(host);
+CREATE INDEX IF NOT EXISTS idx_feed_items_url ON feed_items(url);
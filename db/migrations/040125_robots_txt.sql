@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS robots_txt (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    origin TEXT NOT NULL,
+    status_code INTEGER,
+    body TEXT NOT NULL DEFAULT '',
+    fetched_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(origin)
+);
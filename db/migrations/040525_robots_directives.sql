@@ -0,0 +1 @@
+ALTER TABLE visited ADD COLUMN robots_directives TEXT;
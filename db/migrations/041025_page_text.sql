@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS page_text (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    title TEXT,
+    text TEXT NOT NULL,
+    word_count INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(url),
+    FOREIGN KEY(url) REFERENCES visited(url) ON DELETE CASCADE
+);
@@ -0,0 +1,141 @@
+use reqwest::Url;
+
+use crate::config;
+
+// Run this before storing or comparing a URL, so the same page is only stored and crawled once.
+// The host is lowercased and default ports are dropped when the URL is parsed. On top of that we:
+// - Drop the fragment.
+// - Normalise percent-encoding, decoding unreserved characters and uppercasing the hex digits of the rest.
+// - Drop query parameters matching CANONICAL_DROP_PARAMS, unless they match CANONICAL_KEEP_PARAMS.
+// - Sort the query parameters, if CANONICAL_SORT_QUERY is on.
+// - Fold away the scheme and a leading www., if CANONICAL_FOLD_SCHEME and CANONICAL_FOLD_WWW are on.
+// Anything that isn't a URL, like the STARTING_URL referrer, is returned unchanged.
+pub(crate) fn canonicalize(config: &config::Config, url: &str) -> String {
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return url.to_string(),
+    };
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return url.to_string(),
+    };
+
+    let mut canonical = String::new();
+    if !config.canonical_fold_scheme {
+        canonical.push_str(url.scheme());
+        canonical.push_str("://");
+    }
+    let host = if config.canonical_fold_www { host.strip_prefix("www.").unwrap_or(host) } else { host };
+    canonical.push_str(host);
+    if let Some(port) = url.port() {
+        canonical.push_str(&format!(":{}", port));
+    }
+    canonical.push_str(&normalize_percent_encoding(url.path()));
+
+    if let Some(query) = url.query() {
+        let mut params: Vec<String> = query.split('&')
+            .filter(|param| !param.is_empty())
+            .map(normalize_percent_encoding)
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or("").to_lowercase();
+                !matches_any(&config.canonical_drop_params, &name) || matches_any(&config.canonical_keep_params, &name)
+            })
+            .collect();
+        if config.canonical_sort_query {
+            params.sort();
+        }
+        if !params.is_empty() {
+            canonical.push('?');
+            canonical.push_str(&params.join("&"));
+        }
+    }
+    canonical
+}
+
+// Check a query parameter name against a list of names. A trailing * matches any suffix, so utm_* matches utm_source.
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.to_lowercase();
+        match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        }
+    })
+}
+
+// Decode percent-encoded unreserved characters, and uppercase the hex digits of everything left encoded (RFC 3986 6.2.2).
+fn normalize_percent_encoding(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut normalized = String::with_capacity(value.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() {
+            let decoded = u8::from_str_radix(&value[i + 1..i + 3], 16).unwrap_or(0);
+            if decoded.is_ascii_alphanumeric() || matches!(decoded, b'-' | b'.' | b'_' | b'~') {
+                normalized.push(decoded as char);
+            } else {
+                normalized.push('%');
+                normalized.push_str(&value[i + 1..i + 3].to_uppercase());
+            }
+            i += 3;
+        } else {
+            normalized.push(bytes[i] as char);
+            i += 1;
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> config::Config {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.canonical_sort_query = true;
+        config.canonical_drop_params = vec!["utm_*".to_string(), "sessionid".to_string()];
+        config.canonical_keep_params = Vec::new();
+        config.canonical_fold_www = false;
+        config.canonical_fold_scheme = false;
+        config
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let config = config();
+        let cases = [
+            ("HTTPS://WWW.Example.COM:443/Path/#section", "https://www.example.com/Path/"),
+            ("http://example.com:8080/a", "http://example.com:8080/a"),
+            ("https://example.com/%7euser/%2f%41?q=%4a%2b", "https://example.com/~user/%2FA?q=J%2B"),
+            ("https://example.com/list?page=2", "https://example.com/list?page=2"),
+            ("https://example.com/list?page=3", "https://example.com/list?page=3"),
+            ("https://example.com/?b=2&a=1&&a=0", "https://example.com/?a=0&a=1&b=2"),
+            ("https://example.com/?utm_source=x&UTM_MEDIUM=y&id=5&SessionId=abc", "https://example.com/?id=5"),
+            ("https://example.com/?utm_source=x", "https://example.com/"),
+            ("STARTING_URL", "STARTING_URL"),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(canonicalize(&config, url), expected, "canonicalizing {}", url);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_keep_params_override_drop() {
+        let mut config = config();
+        config.canonical_drop_params = vec!["*".to_string()];
+        config.canonical_keep_params = vec!["page".to_string()];
+        assert_eq!(canonicalize(&config, "https://example.com/list?ref=home&page=2&sort=asc"), "https://example.com/list?page=2");
+    }
+
+    #[test]
+    fn test_canonicalize_folding() {
+        let mut config = config();
+        assert_ne!(canonicalize(&config, "http://www.example.com/a"), canonicalize(&config, "https://example.com/a"));
+        config.canonical_fold_www = true;
+        config.canonical_fold_scheme = true;
+        assert_eq!(canonicalize(&config, "http://www.example.com/a"), "example.com/a");
+        assert_eq!(canonicalize(&config, "https://example.com/a"), "example.com/a");
+        config.canonical_sort_query = false;
+        assert_eq!(canonicalize(&config, "https://example.com/?b=2&a=1"), "example.com/?b=2&a=1");
+    }
+}
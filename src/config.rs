@@ -1,9 +1,18 @@
 use serde::Deserialize;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs;
 use crate::constants;
 use crate::tools;
 
+// An allow or deny rule for URLs, with either a glob or a regex pattern.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UrlRuleConfig {
+    pub action: String,
+    pub glob: Option<String>,
+    pub regex: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     // Site Settings
@@ -11,19 +20,78 @@ pub struct Config {
     pub permitted_domains: Vec<String>,
     pub blacklist_domains: Vec<String>,
     pub free_crawl: bool,
+    pub seeds: Vec<String>,
+    pub seeds_file: String,
+    pub seed_scope: String,
+    pub list_mode: bool,
+
+    // Crawl Strategy Settings
+    pub crawl_strategy: String,
+    pub priority_domains: Vec<String>,
+    pub priority_patterns: Vec<String>,
 
     // Crawler Settings
     pub max_urls_to_visit: usize,
+    pub max_depth: usize,
+    pub max_pages_per_domain: usize,
+    pub domain_page_budgets: HashMap<String, usize>,
+    pub trap_max_repeated_segments: usize,
+    pub trap_max_path_depth: usize,
+    pub trap_max_url_length: usize,
+    pub trap_max_query_variants: usize,
+    pub trap_max_urls_per_pattern: usize,
+    pub trap_pattern_budgets: HashMap<String, usize>,
+    pub url_rules: Vec<UrlRuleConfig>,
+    pub url_rules_default: String,
+    pub discover_sitemaps: bool,
+    pub sitemap_max_urls: usize,
+    pub discover_feeds: bool,
+    pub feed_max_items: usize,
     pub max_threads: usize,
+    pub max_concurrent_requests: usize,
     pub rotate_user_agents: bool,
     pub respect_robots: bool,
+    pub robots_user_agent: String,
+    pub respect_nofollow_links: bool,
+    pub robots_cache_ttl_secs: u64,
+    pub robots_max_bytes: usize,
     pub crawler_timeout: u64,
     pub crawler_request_timeout: u64,
+    pub crawler_connect_timeout: u64,
     pub crawler_request_delay_ms: u64,
+    pub max_crawl_delay_ms: u64,
+    pub auto_throttle: bool,
+    pub auto_throttle_min_delay_ms: u64,
+    pub auto_throttle_max_delay_ms: u64,
+    pub auto_throttle_target_concurrency: f64,
+    pub retry_max_attempts: u32,
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    pub retry_status_codes: Vec<u16>,
+    pub retry_error_kinds: Vec<String>,
+    pub circuit_breaker_threshold: u32,
+    pub circuit_breaker_cooldown_secs: u64,
+
+    // HTTP Client Settings
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: u64,
+    pub default_headers: HashMap<String, String>,
+    pub compression: bool,
+    pub max_redirects: usize,
+    pub recorded_headers: Vec<String>,
+
+    // URL Canonicalization Settings
+    pub canonical_sort_query: bool,
+    pub canonical_drop_params: Vec<String>,
+    pub canonical_keep_params: Vec<String>,
+    pub canonical_fold_www: bool,
+    pub canonical_fold_scheme: bool,
 
     // Data Collection Options
     pub collect_html: bool,
     pub collect_images: bool,
+    pub collect_text: bool,
+    pub skip_duplicate_canonicals: bool,
 
     // Logging Options
     pub debug: bool,
@@ -45,15 +113,68 @@ impl Config {
             permitted_domains: constants::PERMITTED_DOMAINS.iter().map(|s| s.to_string()).collect(),
             blacklist_domains: constants::BLACKLIST_DOMAINS.iter().map(|s| s.to_string()).collect(),
             free_crawl: constants::FREE_CRAWL,
+            seeds: constants::SEEDS.iter().map(|s| s.to_string()).collect(),
+            seeds_file: constants::SEEDS_FILE.to_string(),
+            seed_scope: constants::SEED_SCOPE.to_string(),
+            list_mode: constants::LIST_MODE,
             max_urls_to_visit: constants::MAX_URLS_TO_VISIT,
+            max_depth: constants::MAX_DEPTH,
+            max_pages_per_domain: constants::MAX_PAGES_PER_DOMAIN,
+            domain_page_budgets: HashMap::new(),
+            crawl_strategy: constants::CRAWL_STRATEGY.to_string(),
+            priority_domains: constants::PRIORITY_DOMAINS.iter().map(|s| s.to_string()).collect(),
+            priority_patterns: constants::PRIORITY_PATTERNS.iter().map(|s| s.to_string()).collect(),
+            trap_max_repeated_segments: constants::TRAP_MAX_REPEATED_SEGMENTS,
+            trap_max_path_depth: constants::TRAP_MAX_PATH_DEPTH,
+            trap_max_url_length: constants::TRAP_MAX_URL_LENGTH,
+            trap_max_query_variants: constants::TRAP_MAX_QUERY_VARIANTS,
+            trap_max_urls_per_pattern: constants::TRAP_MAX_URLS_PER_PATTERN,
+            trap_pattern_budgets: HashMap::new(),
+            url_rules: Vec::new(),
+            url_rules_default: constants::URL_RULES_DEFAULT.to_string(),
+            discover_sitemaps: constants::DISCOVER_SITEMAPS,
+            sitemap_max_urls: constants::SITEMAP_MAX_URLS,
+            discover_feeds: constants::DISCOVER_FEEDS,
+            feed_max_items: constants::FEED_MAX_ITEMS,
             max_threads: constants::MAX_THREADS,
+            max_concurrent_requests: constants::MAX_CONCURRENT_REQUESTS,
             rotate_user_agents: constants::ROTATE_USER_AGENTS,
             respect_robots: constants::RESPECT_ROBOTS,
+            robots_user_agent: constants::ROBOTS_USER_AGENT.to_string(),
+            respect_nofollow_links: constants::RESPECT_NOFOLLOW_LINKS,
+            robots_cache_ttl_secs: constants::ROBOTS_CACHE_TTL_SECS,
+            robots_max_bytes: constants::ROBOTS_MAX_BYTES,
             crawler_timeout: constants::CRAWLER_TIMEOUT,
             crawler_request_timeout: constants::CRAWLER_REQUEST_TIMEOUT,
+            crawler_connect_timeout: constants::CRAWLER_CONNECT_TIMEOUT,
             crawler_request_delay_ms: constants::CRAWLER_REQUEST_DELAY_MS,
+            max_crawl_delay_ms: constants::MAX_CRAWL_DELAY_MS,
+            auto_throttle: constants::AUTO_THROTTLE,
+            auto_throttle_min_delay_ms: constants::AUTO_THROTTLE_MIN_DELAY_MS,
+            auto_throttle_max_delay_ms: constants::AUTO_THROTTLE_MAX_DELAY_MS,
+            auto_throttle_target_concurrency: constants::AUTO_THROTTLE_TARGET_CONCURRENCY,
+            retry_max_attempts: constants::RETRY_MAX_ATTEMPTS,
+            retry_base_delay_ms: constants::RETRY_BASE_DELAY_MS,
+            retry_max_delay_ms: constants::RETRY_MAX_DELAY_MS,
+            retry_status_codes: constants::RETRY_STATUS_CODES.to_vec(),
+            retry_error_kinds: constants::RETRY_ERROR_KINDS.iter().map(|s| s.to_string()).collect(),
+            circuit_breaker_threshold: constants::CIRCUIT_BREAKER_THRESHOLD,
+            circuit_breaker_cooldown_secs: constants::CIRCUIT_BREAKER_COOLDOWN_SECS,
+            pool_max_idle_per_host: constants::POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout: constants::POOL_IDLE_TIMEOUT,
+            default_headers: constants::DEFAULT_HEADERS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            compression: constants::COMPRESSION,
+            max_redirects: constants::MAX_REDIRECTS,
+            recorded_headers: constants::RECORDED_HEADERS.iter().map(|s| s.to_string()).collect(),
+            canonical_sort_query: constants::CANONICAL_SORT_QUERY,
+            canonical_drop_params: constants::CANONICAL_DROP_PARAMS.iter().map(|s| s.to_string()).collect(),
+            canonical_keep_params: constants::CANONICAL_KEEP_PARAMS.iter().map(|s| s.to_string()).collect(),
+            canonical_fold_www: constants::CANONICAL_FOLD_WWW,
+            canonical_fold_scheme: constants::CANONICAL_FOLD_SCHEME,
             collect_html: constants::COLLECT_HTML,
             collect_images: constants::COLLECT_IMAGES,
+            collect_text: constants::COLLECT_TEXT,
+            skip_duplicate_canonicals: constants::SKIP_DUPLICATE_CANONICALS,
             debug: constants::DEBUG,
             live_logging: constants::LIVE_LOGGING,
             sqlite_enabled: constants::SQLITE_ENABLED,
@@ -85,33 +206,198 @@ impl Config {
                     if let Some(free_crawl) = json_config.get("free_crawl").and_then(Value::as_bool) {
                         config.free_crawl = free_crawl;
                     }
+                    if let Some(seeds) = json_config.get("seeds").and_then(Value::as_array) {
+                        config.seeds = seeds.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(seeds_file) = json_config.get("seeds_file").and_then(Value::as_str) {
+                        config.seeds_file = seeds_file.to_string();
+                    }
+                    if let Some(seed_scope) = json_config.get("seed_scope").and_then(Value::as_str) {
+                        config.seed_scope = seed_scope.to_string();
+                    }
+                    if let Some(list_mode) = json_config.get("list_mode").and_then(Value::as_bool) {
+                        config.list_mode = list_mode;
+                    }
                     if let Some(max_urls_to_visit) = json_config.get("max_urls_to_visit").and_then(Value::as_u64) {
                         config.max_urls_to_visit = max_urls_to_visit as usize;
                     }
+                    if let Some(max_depth) = json_config.get("max_depth").and_then(Value::as_u64) {
+                        config.max_depth = max_depth as usize;
+                    }
+                    if let Some(max_pages_per_domain) = json_config.get("max_pages_per_domain").and_then(Value::as_u64) {
+                        config.max_pages_per_domain = max_pages_per_domain as usize;
+                    }
+                    if let Some(domain_page_budgets) = json_config.get("domain_page_budgets").and_then(Value::as_object) {
+                        config.domain_page_budgets = domain_page_budgets.iter().filter_map(|(domain, budget)| budget.as_u64().map(|b| (domain.to_string(), b as usize))).collect();
+                    }
+                    if let Some(crawl_strategy) = json_config.get("crawl_strategy").and_then(Value::as_str) {
+                        config.crawl_strategy = crawl_strategy.to_string();
+                    }
+                    if let Some(priority_domains) = json_config.get("priority_domains").and_then(Value::as_array) {
+                        config.priority_domains = priority_domains.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(priority_patterns) = json_config.get("priority_patterns").and_then(Value::as_array) {
+                        config.priority_patterns = priority_patterns.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(trap_max_repeated_segments) = json_config.get("trap_max_repeated_segments").and_then(Value::as_u64) {
+                        config.trap_max_repeated_segments = trap_max_repeated_segments as usize;
+                    }
+                    if let Some(trap_max_path_depth) = json_config.get("trap_max_path_depth").and_then(Value::as_u64) {
+                        config.trap_max_path_depth = trap_max_path_depth as usize;
+                    }
+                    if let Some(trap_max_url_length) = json_config.get("trap_max_url_length").and_then(Value::as_u64) {
+                        config.trap_max_url_length = trap_max_url_length as usize;
+                    }
+                    if let Some(trap_max_query_variants) = json_config.get("trap_max_query_variants").and_then(Value::as_u64) {
+                        config.trap_max_query_variants = trap_max_query_variants as usize;
+                    }
+                    if let Some(trap_max_urls_per_pattern) = json_config.get("trap_max_urls_per_pattern").and_then(Value::as_u64) {
+                        config.trap_max_urls_per_pattern = trap_max_urls_per_pattern as usize;
+                    }
+                    if let Some(trap_pattern_budgets) = json_config.get("trap_pattern_budgets").and_then(Value::as_object) {
+                        config.trap_pattern_budgets = trap_pattern_budgets.iter().filter_map(|(pattern, budget)| budget.as_u64().map(|b| (pattern.to_string(), b as usize))).collect();
+                    }
+                    if let Some(url_rules) = json_config.get("url_rules").and_then(Value::as_array) {
+                        config.url_rules = url_rules.iter().filter_map(|rule| match serde_json::from_value(rule.clone()) {
+                            Ok(rule) => Some(rule),
+                            Err(err) => {
+                                tools::debug_log(true, &format!("Ignoring invalid URL rule {}: {}", rule, err));
+                                None
+                            }
+                        }).collect();
+                    }
+                    if let Some(url_rules_default) = json_config.get("url_rules_default").and_then(Value::as_str) {
+                        config.url_rules_default = url_rules_default.to_string();
+                    }
+                    if let Some(discover_sitemaps) = json_config.get("discover_sitemaps").and_then(Value::as_bool) {
+                        config.discover_sitemaps = discover_sitemaps;
+                    }
+                    if let Some(sitemap_max_urls) = json_config.get("sitemap_max_urls").and_then(Value::as_u64) {
+                        config.sitemap_max_urls = sitemap_max_urls as usize;
+                    }
+                    if let Some(discover_feeds) = json_config.get("discover_feeds").and_then(Value::as_bool) {
+                        config.discover_feeds = discover_feeds;
+                    }
+                    if let Some(feed_max_items) = json_config.get("feed_max_items").and_then(Value::as_u64) {
+                        config.feed_max_items = feed_max_items as usize;
+                    }
                     if let Some(max_threads) = json_config.get("max_threads").and_then(Value::as_u64) {
                         config.max_threads = max_threads as usize;
                     }
+                    if let Some(max_concurrent_requests) = json_config.get("max_concurrent_requests").and_then(Value::as_u64) {
+                        config.max_concurrent_requests = max_concurrent_requests as usize;
+                    }
                     if let Some(rotate_user_agents) = json_config.get("rotate_user_agents").and_then(Value::as_bool) {
                         config.rotate_user_agents = rotate_user_agents;
                     }
                     if let Some(respect_robots) = json_config.get("respect_robots").and_then(Value::as_bool) {
                         config.respect_robots = respect_robots;
                     }
+                    if let Some(robots_user_agent) = json_config.get("robots_user_agent").and_then(Value::as_str) {
+                        config.robots_user_agent = robots_user_agent.to_string();
+                    }
+                    if let Some(respect_nofollow_links) = json_config.get("respect_nofollow_links").and_then(Value::as_bool) {
+                        config.respect_nofollow_links = respect_nofollow_links;
+                    }
+                    if let Some(robots_cache_ttl_secs) = json_config.get("robots_cache_ttl_secs").and_then(Value::as_u64) {
+                        config.robots_cache_ttl_secs = robots_cache_ttl_secs;
+                    }
+                    if let Some(robots_max_bytes) = json_config.get("robots_max_bytes").and_then(Value::as_u64) {
+                        config.robots_max_bytes = robots_max_bytes as usize;
+                    }
                     if let Some(crawler_timeout) = json_config.get("crawler_timeout").and_then(Value::as_u64) {
                         config.crawler_timeout = crawler_timeout;
                     }
                     if let Some(crawler_request_timeout) = json_config.get("crawler_request_timeout").and_then(Value::as_u64) {
                         config.crawler_request_timeout = crawler_request_timeout;
                     }
+                    if let Some(crawler_connect_timeout) = json_config.get("crawler_connect_timeout").and_then(Value::as_u64) {
+                        config.crawler_connect_timeout = crawler_connect_timeout;
+                    }
                     if let Some(crawler_request_delay_ms) = json_config.get("crawler_request_delay_ms").and_then(Value::as_u64) {
                         config.crawler_request_delay_ms = crawler_request_delay_ms;
                     }
+                    if let Some(max_crawl_delay_ms) = json_config.get("max_crawl_delay_ms").and_then(Value::as_u64) {
+                        config.max_crawl_delay_ms = max_crawl_delay_ms;
+                    }
+                    if let Some(auto_throttle) = json_config.get("auto_throttle").and_then(Value::as_bool) {
+                        config.auto_throttle = auto_throttle;
+                    }
+                    if let Some(auto_throttle_min_delay_ms) = json_config.get("auto_throttle_min_delay_ms").and_then(Value::as_u64) {
+                        config.auto_throttle_min_delay_ms = auto_throttle_min_delay_ms;
+                    }
+                    if let Some(auto_throttle_max_delay_ms) = json_config.get("auto_throttle_max_delay_ms").and_then(Value::as_u64) {
+                        config.auto_throttle_max_delay_ms = auto_throttle_max_delay_ms;
+                    }
+                    if let Some(auto_throttle_target_concurrency) = json_config.get("auto_throttle_target_concurrency").and_then(Value::as_f64) {
+                        config.auto_throttle_target_concurrency = auto_throttle_target_concurrency;
+                    }
+                    if let Some(retry_max_attempts) = json_config.get("retry_max_attempts").and_then(Value::as_u64) {
+                        config.retry_max_attempts = retry_max_attempts as u32;
+                    }
+                    if let Some(retry_base_delay_ms) = json_config.get("retry_base_delay_ms").and_then(Value::as_u64) {
+                        config.retry_base_delay_ms = retry_base_delay_ms;
+                    }
+                    if let Some(retry_max_delay_ms) = json_config.get("retry_max_delay_ms").and_then(Value::as_u64) {
+                        config.retry_max_delay_ms = retry_max_delay_ms;
+                    }
+                    if let Some(retry_status_codes) = json_config.get("retry_status_codes").and_then(Value::as_array) {
+                        config.retry_status_codes = retry_status_codes.iter().filter_map(|x| x.as_u64().map(|code| code as u16)).collect();
+                    }
+                    if let Some(retry_error_kinds) = json_config.get("retry_error_kinds").and_then(Value::as_array) {
+                        config.retry_error_kinds = retry_error_kinds.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(circuit_breaker_threshold) = json_config.get("circuit_breaker_threshold").and_then(Value::as_u64) {
+                        config.circuit_breaker_threshold = circuit_breaker_threshold as u32;
+                    }
+                    if let Some(circuit_breaker_cooldown_secs) = json_config.get("circuit_breaker_cooldown_secs").and_then(Value::as_u64) {
+                        config.circuit_breaker_cooldown_secs = circuit_breaker_cooldown_secs;
+                    }
+                    if let Some(pool_max_idle_per_host) = json_config.get("pool_max_idle_per_host").and_then(Value::as_u64) {
+                        config.pool_max_idle_per_host = pool_max_idle_per_host as usize;
+                    }
+                    if let Some(pool_idle_timeout) = json_config.get("pool_idle_timeout").and_then(Value::as_u64) {
+                        config.pool_idle_timeout = pool_idle_timeout;
+                    }
+                    if let Some(default_headers) = json_config.get("default_headers").and_then(Value::as_object) {
+                        config.default_headers = default_headers.iter().filter_map(|(name, value)| value.as_str().map(|v| (name.to_string(), v.to_string()))).collect();
+                    }
+                    if let Some(compression) = json_config.get("compression").and_then(Value::as_bool) {
+                        config.compression = compression;
+                    }
+                    if let Some(max_redirects) = json_config.get("max_redirects").and_then(Value::as_u64) {
+                        config.max_redirects = max_redirects as usize;
+                    }
+                    if let Some(recorded_headers) = json_config.get("recorded_headers").and_then(Value::as_array) {
+                        config.recorded_headers = recorded_headers.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(canonical_sort_query) = json_config.get("canonical_sort_query").and_then(Value::as_bool) {
+                        config.canonical_sort_query = canonical_sort_query;
+                    }
+                    if let Some(canonical_drop_params) = json_config.get("canonical_drop_params").and_then(Value::as_array) {
+                        config.canonical_drop_params = canonical_drop_params.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(canonical_keep_params) = json_config.get("canonical_keep_params").and_then(Value::as_array) {
+                        config.canonical_keep_params = canonical_keep_params.iter().map(|x| x.as_str().unwrap_or("").to_string()).collect();
+                    }
+                    if let Some(canonical_fold_www) = json_config.get("canonical_fold_www").and_then(Value::as_bool) {
+                        config.canonical_fold_www = canonical_fold_www;
+                    }
+                    if let Some(canonical_fold_scheme) = json_config.get("canonical_fold_scheme").and_then(Value::as_bool) {
+                        config.canonical_fold_scheme = canonical_fold_scheme;
+                    }
                     if let Some(collect_html) = json_config.get("collect_html").and_then(Value::as_bool) {
                         config.collect_html = collect_html;
                     }
                     if let Some(collect_images) = json_config.get("collect_images").and_then(Value::as_bool) {
                         config.collect_images = collect_images;
                     }
+                    if let Some(collect_text) = json_config.get("collect_text").and_then(Value::as_bool) {
+                        config.collect_text = collect_text;
+                    }
+                    if let Some(skip_duplicate_canonicals) = json_config.get("skip_duplicate_canonicals").and_then(Value::as_bool) {
+                        config.skip_duplicate_canonicals = skip_duplicate_canonicals;
+                    }
                     if let Some(debug) = json_config.get("debug").and_then(Value::as_bool) {
                         config.debug = debug;
                     }
@@ -3,19 +3,87 @@ pub(crate) const STARTING_URL: &str = "https://www.cnn.com";
 pub(crate) const PERMITTED_DOMAINS: [&str; 1] = ["www.cnn.com"];
 pub(crate) const BLACKLIST_DOMAINS: [&str; 0] = [];
 pub(crate) const FREE_CRAWL: bool = true;
+pub(crate) const SEEDS: [&str; 0] = [];
+pub(crate) const SEEDS_FILE: &str = "";
+pub(crate) const SEED_SCOPE: &str = "none"; // none, host or domain
+pub(crate) const LIST_MODE: bool = false;
+
+// Crawl Strategy Settings
+pub(crate) const CRAWL_STRATEGY: &str = "bfs"; // bfs, dfs, or priority
+pub(crate) const PRIORITY_DOMAINS: [&str; 0] = [];
+pub(crate) const PRIORITY_PATTERNS: [&str; 0] = [];
 
 // Crawler Settings
 pub(crate) const MAX_URLS_TO_VISIT: usize = 500;
+pub(crate) const MAX_DEPTH: usize = 0; // 0 for no limit
+pub(crate) const MAX_PAGES_PER_DOMAIN: usize = 0; // 0 for no limit
+
+// Spider Trap Settings, 0 turns each check off
+pub(crate) const TRAP_MAX_REPEATED_SEGMENTS: usize = 2;
+pub(crate) const TRAP_MAX_PATH_DEPTH: usize = 12;
+pub(crate) const TRAP_MAX_URL_LENGTH: usize = 2048;
+pub(crate) const TRAP_MAX_QUERY_VARIANTS: usize = 50;
+pub(crate) const TRAP_MAX_URLS_PER_PATTERN: usize = 100;
+
+// URL Rules, see rules.rs
+pub(crate) const URL_RULES_DEFAULT: &str = "allow"; // allow or deny
+
+// Sitemap Settings
+pub(crate) const DISCOVER_SITEMAPS: bool = false;
+pub(crate) const SITEMAP_MAX_URLS: usize = 10000; // per host, 0 for no limit
+
+// Feed Settings
+pub(crate) const DISCOVER_FEEDS: bool = false;
+pub(crate) const FEED_MAX_ITEMS: usize = 100; // per feed, 0 for no limit
+
 pub(crate) const MAX_THREADS: usize = 10;
+pub(crate) const MAX_CONCURRENT_REQUESTS: usize = 50;
 pub(crate) const ROTATE_USER_AGENTS: bool = true;
 pub(crate) const RESPECT_ROBOTS: bool = true;
+pub(crate) const ROBOTS_USER_AGENT: &str = "crab-crawler"; // matched against robots.txt groups, not sent
+pub(crate) const RESPECT_NOFOLLOW_LINKS: bool = false; // rel=nofollow, ugc and sponsored
+pub(crate) const ROBOTS_CACHE_TTL_SECS: u64 = 86400;
+pub(crate) const ROBOTS_MAX_BYTES: usize = 512000; // 0 for no limit
 pub(crate) const CRAWLER_TIMEOUT: u64 = 3600; 
 pub(crate) const CRAWLER_REQUEST_TIMEOUT: u64 = 60; 
+pub(crate) const CRAWLER_CONNECT_TIMEOUT: u64 = 10;
 pub(crate) const CRAWLER_REQUEST_DELAY_MS: u64 = 1000; 
+pub(crate) const MAX_CRAWL_DELAY_MS: u64 = 30000;
+pub(crate) const AUTO_THROTTLE: bool = false;
+pub(crate) const AUTO_THROTTLE_MIN_DELAY_MS: u64 = 250;
+pub(crate) const AUTO_THROTTLE_MAX_DELAY_MS: u64 = 60000;
+pub(crate) const AUTO_THROTTLE_TARGET_CONCURRENCY: f64 = 1.0;
+pub(crate) const RETRY_MAX_ATTEMPTS: u32 = 3;
+pub(crate) const RETRY_BASE_DELAY_MS: u64 = 1000;
+pub(crate) const RETRY_MAX_DELAY_MS: u64 = 60000;
+pub(crate) const RETRY_STATUS_CODES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+pub(crate) const RETRY_ERROR_KINDS: [&str; 3] = ["timeout", "connect", "body"];
+pub(crate) const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+pub(crate) const CIRCUIT_BREAKER_COOLDOWN_SECS: u64 = 300;
+
+// HTTP Client Settings
+pub(crate) const POOL_MAX_IDLE_PER_HOST: usize = 10;
+pub(crate) const POOL_IDLE_TIMEOUT: u64 = 90;
+pub(crate) const DEFAULT_HEADERS: [(&str, &str); 2] = [
+    ("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+    ("Accept-Language", "en-US,en;q=0.5"),
+];
+pub(crate) const COMPRESSION: bool = true;
+pub(crate) const MAX_REDIRECTS: usize = 10;
+pub(crate) const RECORDED_HEADERS: [&str; 6] = ["server", "cache-control", "last-modified", "etag", "retry-after", "x-robots-tag"];
+
+// URL Canonicalization Settings
+pub(crate) const CANONICAL_SORT_QUERY: bool = true;
+pub(crate) const CANONICAL_DROP_PARAMS: [&str; 9] = ["utm_*", "fbclid", "gclid", "msclkid", "mc_eid", "sessionid", "jsessionid", "phpsessid", "sid"];
+pub(crate) const CANONICAL_KEEP_PARAMS: [&str; 0] = [];
+pub(crate) const CANONICAL_FOLD_WWW: bool = false;
+pub(crate) const CANONICAL_FOLD_SCHEME: bool = false;
 
 // Data Collection Options
 pub(crate) const COLLECT_HTML: bool = false;
 pub(crate) const COLLECT_IMAGES: bool = true;
+pub(crate) const COLLECT_TEXT: bool = false;
+pub(crate) const SKIP_DUPLICATE_CANONICALS: bool = false;
 // pub(crate) const COLLECT_PDFS: bool = true;
 // pub(crate) const COLLECT_SCREENSHOTS: bool = true;
 
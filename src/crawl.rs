@@ -1,128 +1,652 @@
-use reqwest::Url;
+use reqwest::{Client, Url};
 use rusqlite::Connection;
 use std::sync::{Arc, Mutex, atomic::Ordering};
-use rayon::{ThreadPool, prelude::*};
 use chrono::Local;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinSet};
 
 use crate::config;
 use crate::sqlite;
 use crate::tools;
 use crate::http;
 use crate::data;
+use crate::frontier;
+use crate::politeness;
+use crate::retry;
+use crate::health;
+use crate::canonical;
+use crate::sitemaps;
+use crate::feeds;
+use crate::robots;
+use crate::text;
 
 pub struct Crawler {
     config: config::Config,
     db_conn: Arc<Mutex<Connection>>,
-    pool: Arc<ThreadPool>,
+    client: Client,
+    requests: Semaphore,
     seen: Arc<Mutex<HashSet<String>>>,
+    // Canonical URLs whose content has been stored this run.
+    captured: Mutex<HashSet<String>>,
+    queue: Mutex<frontier::CrawlQueue>,
+    limits: frontier::CrawlLimits,
+    filters: tools::LinkFilters,
+    politeness: politeness::PolitenessScheduler,
+    retry: retry::RetryPolicy,
+    health: health::DomainHealth,
+    sitemaps: sitemaps::SitemapDiscovery,
+    feeds: feeds::FeedDiscovery,
 }
 
 impl Crawler {
-    pub fn new(config:  config::Config, db_conn: Connection, pool: Arc<ThreadPool>) -> Self {
+    pub fn new(config:  config::Config, db_conn: Connection, client: Client, seeds: &[Url]) -> Self {
         let db_conn = Arc::new(Mutex::new(db_conn));
+        let requests = Semaphore::new(config.max_concurrent_requests.max(1));
         let seen = Arc::new(Mutex::new(HashSet::new()));
-        Crawler { config, db_conn, pool, seen }
+        let captured = Mutex::new(HashSet::new());
+        let queue = Mutex::new(frontier::CrawlQueue::new(&config));
+        let limits = frontier::CrawlLimits::new(&config);
+        let filters = tools::LinkFilters::new(&config, seeds);
+        let politeness = politeness::PolitenessScheduler::new(&config);
+        let retry = retry::RetryPolicy::new(&config);
+        let health = health::DomainHealth::new(&config);
+        let sitemaps = sitemaps::SitemapDiscovery::new();
+        let feeds = feeds::FeedDiscovery::new();
+        Crawler { config, db_conn, client, requests, seen, captured, queue, limits, filters, politeness, retry, health, sitemaps, feeds }
     }
 
-    // Recursively crawl a website, with Depth-First Search.
-    pub fn crawl_website_dfs(&self, target_url: &Url, referrer_url: &String) -> bool {
-        if data::URLS_VISITED.load(Ordering::SeqCst) >= self.config.max_urls_to_visit {
-            // Base Case
-            return false;
-        }
-        
+    // Crawl a single page, and return the links that should be queued next, or the page itself if it should be retried.
+    pub async fn crawl_page(&self, entry: &data::FrontierEntry) -> data::PageOutcome {
+        let target_url = &entry.url;
+        let referrer_url = &entry.referrer;
+
         // Format the visited URL for storage and comparison
-        let formatted_target_url = tools::format_url_for_storage(target_url.to_string());
-        let formatted_referrer_url = tools::format_url_for_storage(referrer_url.clone());
-        { // Scope the mutable borrow of db_conn, otherwise it will stay in scope due to recursion below.
-            let mut conn = self.db_conn.lock().unwrap();
+        let formatted_target_url = canonical::canonicalize(&self.config, target_url.as_str());
+        let formatted_referrer_url = canonical::canonicalize(&self.config, referrer_url);
+        {
+            let conn = self.db_conn.lock().unwrap();
             // Store the visited URL
-            let visited_site = data::VisitedSite::new(formatted_target_url.clone(), formatted_referrer_url.clone(), Local::now());
-            data::URLS_VISITED.fetch_add(1, Ordering::SeqCst);
-            if let Err(e) = sqlite::insert_visited_site(&mut conn, visited_site.clone()) {
+            let visited_site = data::VisitedSite::new(formatted_target_url.clone(), formatted_referrer_url.clone(), Local::now(), entry.depth, target_url.host_str().map(|h| h.to_string()));
+            if let Err(e) = sqlite::insert_visited_site(&conn, visited_site.clone()) {
                 tools::debug_log(self.config.debug, &format!("Failed to insert visited URL {} into SQLite: {}", formatted_target_url, e));
             }
+            self.set_frontier_state(&conn, target_url, data::FrontierState::Fetching);
         }
-    
-        // Set the delay before continuing after the request is complete.
-        let _defer = tools::Defer::new(|| {
-            std::thread::sleep(std::time::Duration::from_millis(self.config.crawler_request_delay_ms));
-        });
-    
+
         // Fetch the HTML content of the page
         if self.config.live_logging {
             println!("Visiting {} from {}", target_url, referrer_url);
         }
-        let html = match http::fetch_html(&self.config, &self.db_conn, target_url.clone()) {
-            Ok(html) => html,
-            Err(e) => {
-                tools::debug_log(self.config.debug, &format!("Failed to fetch HTML from {}: {}", target_url, e));
-                return true;
+        let result = match self.fetch_page(target_url, entry.attempt).await {
+            Some(result) => result,
+            None => {
+                tools::debug_log(self.config.debug, &format!("Holding {} until its host is ready", target_url));
+                self.set_frontier_state(&self.db_conn.lock().unwrap(), target_url, data::FrontierState::Queued);
+                return data::PageOutcome::Deferred(Box::new(entry.clone()));
             }
         };
-    
-        // Parse the HTML content into a Html object
-        let doc = match tools::parse_html(&html) {
-            Ok(doc) => doc,
+        let (html, response) = match result {
+            Ok(page) => page,
             Err(e) => {
-                tools::debug_log(self.config.debug, &format!("Failed to parse HTML from {}: {}", target_url, e));
-                return true;
+                // Transient failures are retried with backoff, from the queue rather than by sleeping here.
+                // If the host's circuit breaker tripped, the retry also waits for it to cool down.
+                if let Some(delay) = self.retry.next_delay(entry.attempt, &e) {
+                    match self.health.cooldown_remaining(target_url.host_str().unwrap_or("")) {
+                        Some(cooldown) => tools::debug_log(self.config.debug, &format!("Attempt {} for {} failed: {}. Retrying after its host cools down in {:?}", entry.attempt, target_url, e, cooldown.max(delay))),
+                        None => tools::debug_log(self.config.debug, &format!("Attempt {} for {} failed: {}. Retrying in {:?}", entry.attempt, target_url, e, delay)),
+                    }
+                    self.set_frontier_state(&self.db_conn.lock().unwrap(), target_url, data::FrontierState::Queued);
+                    return data::PageOutcome::Deferred(Box::new(entry.clone().retry_in(delay)));
+                }
+                tools::debug_log(self.config.debug, &format!("Failed to fetch HTML from {}: {}", target_url, e));
+                self.set_frontier_state(&self.db_conn.lock().unwrap(), target_url, data::FrontierState::Failed);
+                return data::PageOutcome::Crawled(Vec::new());
             }
         };
-    
-        // Extract the links from the Html object
-        // TODO: Handle Sitemaps
-        let site_links = match tools::extract_links(&doc){
-            Ok(links) => links,
+        self.set_frontier_state(&self.db_conn.lock().unwrap(), target_url, data::FrontierState::Fetched);
+
+        // Parse the HTML, and extract the links and text, on the blocking pool so parsing doesn't stall the async requests.
+        let collect_text = self.config.collect_text;
+        let parsed = task::spawn_blocking(move || {
+            let doc = tools::parse_html(&html).map_err(|e| format!("Failed to parse HTML: {}", e))?;
+            let links = tools::extract_links(&doc).map_err(|e| format!("Failed to extract links: {}", e))?;
+            let page_text = collect_text.then(|| text::extract_page_text(&doc));
+            Ok::<_, String>((links, page_text, html))
+        }).await;
+        let (mut site_links, page_text, html) = match parsed {
+            Ok(Ok(parsed)) => parsed,
+            Ok(Err(e)) => {
+                tools::debug_log(self.config.debug, &format!("{} from {}", e, target_url));
+                return data::PageOutcome::Crawled(Vec::new());
+            }
             Err(e) => {
-                tools::debug_log(self.config.debug, &format!("Failed to extract links from {}: {}", target_url, e));
-                return true;
+                tools::debug_log(self.config.debug, &format!("Failed to parse HTML from {}: {}", target_url, e));
+                return data::PageOutcome::Crawled(Vec::new());
             }
         };
-    
+
+        // Relative links are resolved against the page's <base href>, or where the page ended up after any redirects.
+        let page_url = response.final_url.clone().unwrap_or_else(|| target_url.clone());
+        let base_url = tools::resolve_base_url(site_links.base_href.as_deref(), &page_url);
+        if base_url != page_url {
+            tools::debug_log(self.config.debug, &format!("Resolving links on {} against its base URL {}", page_url, base_url));
+        }
+
+        // Record the page's canonical URL, and skip storing its content if another page already captured it.
+        let is_duplicate = self.record_canonical(&formatted_target_url, site_links.canonical_href.take(), response.canonical_link.as_deref(), &base_url, &page_url);
+        // A noindex page is crawled, but its content isn't stored.
+        let directives = self.record_robots_directives(&formatted_target_url, &response.robots_tags, &site_links.meta_tags);
+        if directives.noindex {
+            tools::debug_log(self.config.debug, &format!("Not storing {}, it's marked noindex", formatted_target_url));
+        }
+        let store_content = !is_duplicate && !directives.noindex;
+        if self.config.collect_html && store_content {
+            http::save_html(&self.config, &self.db_conn, target_url, &html);
+        }
+        if let Some(page_text) = page_text.filter(|_| store_content) {
+            self.save_page_text(&formatted_target_url, &page_text);
+        }
+
+        // None of a nofollow page's links are followed. Otherwise, links marked nofollow, ugc or sponsored can be skipped.
+        if directives.nofollow {
+            tools::debug_log(self.config.debug, &format!("Not following the links on {}, it's marked nofollow", formatted_target_url));
+            site_links.link_links.clear();
+            site_links.feed_links.clear();
+        } else if self.config.respect_nofollow_links && !site_links.nofollow_links.is_empty() {
+            tools::debug_log(self.config.debug, &format!("Not following {} nofollow links on {}", site_links.nofollow_links.len(), formatted_target_url));
+            let nofollow_links: HashSet<String> = std::mem::take(&mut site_links.nofollow_links).into_iter().collect();
+            site_links.link_links.retain(|link| !nofollow_links.contains(link));
+        }
+
+        // Queue the items in any feeds the page links to. This runs before the page's own links are filtered,
+        // so articles linked from both keep their publication dates.
+        let feed_links = std::mem::take(&mut site_links.feed_links);
+        let mut next_entries = Vec::new();
+        if self.config.discover_feeds && !self.config.list_mode && !feed_links.is_empty() {
+            next_entries = self.crawl_feeds(entry, feed_links, &base_url, &formatted_target_url).await;
+        }
+
         // Filter links to only include those that are valid, and not already seen or completed.
-        let site_urls = tools::filter_links_to_urls(&self.config, site_links, &self.seen, &self.db_conn, &target_url.to_string());
+        let site_urls = tools::filter_links_to_urls(&self.config, &self.client, site_links, &self.seen, &self.db_conn, &base_url, &self.limits, &self.filters, entry).await;
         // Fetch any images from the page
-        if self.config.collect_images {
-            tools::save_image_links(&self.config, &self.pool, &site_urls, &self.db_conn, target_url);
-        }
-        
-        // Recursively crawl each link
-        // This is thread-safe, and will never run more than MAX_THREADS concurrent requests.
-        let success = Arc::new(Mutex::new(true));
-        self.pool.install(|| {
-            // Handle the links
-            let complete = site_urls.link_urls.into_par_iter().try_for_each(|url| {
-                        if !self.crawl_website_dfs( &url, &formatted_target_url) {
-                            *success.lock().unwrap() = false;
-                            return Err(());
-                        }
-                Ok(())
+        if self.config.collect_images && store_content {
+            tools::save_image_links(&self.config, &self.client, &self.requests, &site_urls, &self.db_conn, target_url, &self.politeness).await;
+        }
+
+        // The first time we crawl a page on a host, queue the pages listed in its sitemaps too, one level below the page.
+        // A page at the depth limit doesn't claim its host, so a shallower page can still read the sitemaps later.
+        if self.config.discover_sitemaps && !self.config.list_mode && self.limits.within_depth(entry.depth + 1) {
+            if let Some(host) = target_url.host_str() {
+                if self.sitemaps.claim_host(host) {
+                    next_entries.extend(self.crawl_sitemaps(entry).await);
+                }
+            }
+        }
+
+        // Persist the discovered links, so an interrupted crawl can pick them up again.
+        let conn = self.db_conn.lock().unwrap();
+        for url in site_urls.link_urls.into_iter() {
+            if let Err(e) = sqlite::insert_frontier_url(&conn, &url, &formatted_target_url, entry.depth + 1, entry.seed.as_ref()) {
+                tools::debug_log(self.config.debug, &format!("Failed to insert URL {} into the frontier: {}", url, e));
+            }
+            next_entries.push(data::FrontierEntry::new(url, formatted_target_url.clone(), entry.depth + 1, entry.seed.clone()));
+        }
+
+        // Mark the page as finished in sqlite
+        if let Err(e) = sqlite::mark_url_complete(&conn, &formatted_target_url) {
+            tools::debug_log(self.config.debug, &format!("Failed to mark URL {} as complete in SQLite: {}", formatted_target_url, e));
+        }
+        data::PageOutcome::Crawled(next_entries)
+    }
+
+    // Work out the page's canonical URL from <link rel="canonical">, falling back to the Link header, and store it in visited.
+    // Returns true if SKIP_DUPLICATE_CANONICALS is on and another page already captured the same canonical URL.
+    fn record_canonical(&self, formatted_target_url: &String, canonical_href: Option<String>, canonical_link: Option<&str>, base_url: &Url, page_url: &Url) -> bool {
+        let canonical_url = canonical_href.and_then(|href| http::resolve_url(&self.config, &href, base_url))
+            .or_else(|| canonical_link.and_then(|link| http::resolve_url(&self.config, link, page_url)))
+            .map(|url| canonical::canonicalize(&self.config, url.as_str()));
+        let conn = self.db_conn.lock().unwrap();
+        if let Some(canonical_url) = &canonical_url {
+            if let Err(e) = sqlite::set_canonical_url(&conn, formatted_target_url, canonical_url) {
+                tools::debug_log(self.config.debug, &format!("Failed to record canonical URL for {} in SQLite: {}", formatted_target_url, e));
+            }
+        }
+        if !self.config.skip_duplicate_canonicals {
+            return false;
+        }
+
+        // Pages without a canonical URL are their own canonical. The first page to claim a canonical URL stores its content.
+        let canonical_url = canonical_url.unwrap_or_else(|| formatted_target_url.clone());
+        let is_duplicate = !self.captured.lock().unwrap().insert(canonical_url.clone())
+            || sqlite::is_canonical_captured(&conn, &canonical_url, formatted_target_url).unwrap_or_else(|e| {
+                tools::debug_log(self.config.debug, &format!("Failed to check canonical URL {} in SQLite: {}", canonical_url, e));
+                false
             });
-    
-            // Mark the page as finished in sqlite
-            if complete.is_ok() {
-                if let Err(e) = sqlite::mark_url_complete(&mut self.db_conn.lock().unwrap(), &formatted_target_url) {
-                    tools::debug_log(self.config.debug, &format!("Failed to mark URL {} as complete in SQLite: {}", formatted_target_url, e));
+        if is_duplicate {
+            tools::debug_log(self.config.debug, &format!("Not storing {}, its canonical URL {} was already captured", formatted_target_url, canonical_url));
+        }
+        is_duplicate
+    }
+
+    // Store the page's main text, unless there wasn't any.
+    fn save_page_text(&self, formatted_target_url: &String, page_text: &data::PageText) {
+        if page_text.word_count == 0 {
+            tools::debug_log(self.config.debug, &format!("No text found on {}", formatted_target_url));
+            return;
+        }
+        if let Err(e) = sqlite::upsert_page_text(&self.db_conn.lock().unwrap(), formatted_target_url, page_text) {
+            tools::debug_log(self.config.debug, &format!("Failed to insert text for {} into SQLite: {}", formatted_target_url, e));
+        }
+    }
+
+    // Work out the page's directives from <meta name="robots"> and X-Robots-Tag, and record them in visited.
+    // They're only returned to be obeyed when RESPECT_ROBOTS is on.
+    fn record_robots_directives(&self, formatted_target_url: &String, header_values: &[String], meta_tags: &[(String, String)]) -> robots::RobotsDirectives {
+        let directives = robots::RobotsDirectives::from_page(header_values, meta_tags, &self.config.robots_user_agent);
+        if let Some(recorded) = directives.as_str() {
+            if let Err(e) = sqlite::set_robots_directives(&self.db_conn.lock().unwrap(), formatted_target_url, recorded) {
+                tools::debug_log(self.config.debug, &format!("Failed to record robots directives for {} in SQLite: {}", formatted_target_url, e));
+            }
+        }
+        if !self.config.respect_robots {
+            return robots::RobotsDirectives::default();
+        }
+        directives
+    }
+
+    // Make one attempt at fetching a page. Retries are up to the caller.
+    // Returns None without fetching if the host's circuit breaker tripped, or another request took its turn, since the page was queued.
+    // The page is then held back by the queue, rather than waiting here and holding up a slot other hosts could use.
+    async fn fetch_page(&self, url: &Url, attempt: u32) -> Option<Result<(String, data::ResponseInfo), http::FetchError>> {
+        let host = url.host_str().unwrap_or("");
+        if self.health.cooldown_remaining(host).is_some() {
+            return None;
+        }
+        if !self.politeness.try_take_turn(&self.config, &self.client, &self.db_conn, url).await {
+            return None;
+        }
+        let result = {
+            let _permit = self.requests.acquire().await;
+            http::fetch_html(&self.config, &self.client, &self.db_conn, url.clone(), attempt).await
+        };
+        let error = result.as_ref().err().map(|e| e.to_string());
+        if let Err(e) = sqlite::set_frontier_attempts(&self.db_conn.lock().unwrap(), url, attempt, error.as_deref()) {
+            tools::debug_log(self.config.debug, &format!("Failed to record attempts for {}: {}", url, e));
+        }
+        // Let the adaptive throttle see how the host is responding.
+        match &result {
+            Ok((_, info)) => self.politeness.record_response(&self.config, host, info.ttfb, true),
+            Err(e) => {
+                let latency = match e {
+                    http::FetchError::Status(info) | http::FetchError::TooLarge(info, _) | http::FetchError::NotHtml(info) => info.ttfb,
+                    http::FetchError::Request(_) => Duration::ZERO,
+                };
+                self.politeness.record_response(&self.config, host, latency, !health::DomainHealth::is_failure(e));
+            }
+        }
+        match &result {
+            Err(e) if health::DomainHealth::is_failure(e) => {
+                if self.health.record_failure(host) {
+                    tools::debug_log(self.config.debug, &format!("Circuit breaker tripped for {}, pausing it for {} seconds.", host, self.config.circuit_breaker_cooldown_secs));
+                }
+            }
+            _ => self.health.record_success(host),
+        }
+        Some(result)
+    }
+
+    // Find a host's sitemaps from the Sitemap: lines in its robots.txt and /sitemap.xml, and follow any sitemap indexes.
+    // Their pages go through the same filters as links, and are queued one level below the page with their lastmod and priority.
+    async fn crawl_sitemaps(&self, entry: &data::FrontierEntry) -> Vec<data::FrontierEntry> {
+        let host = match entry.url.host_str() {
+            Some(host) => host,
+            None => return Vec::new(),
+        };
+        let robots_txt = robots::get_robots_txt(&self.config, &self.client, &self.db_conn, &entry.url).await;
+        let mut pending: Vec<(Url, usize)> = sitemaps::parse_robots_sitemaps(robots_txt.body())
+            .into_iter()
+            .map(|url| (url, 0))
+            .collect();
+        if let Ok(default_sitemap) = entry.url.join("/sitemap.xml") {
+            pending.push((default_sitemap, 0));
+        }
+        pending.reverse();
+
+        // Zero means there is no limit.
+        let max_urls = if self.config.sitemap_max_urls == 0 { usize::MAX } else { self.config.sitemap_max_urls };
+        let mut fetched: HashSet<String> = HashSet::new();
+        let mut next_entries = Vec::new();
+        let mut found = 0;
+        while let Some((sitemap_url, nesting)) = pending.pop() {
+            if found >= max_urls {
+                tools::debug_log(self.config.debug, &format!("Reached the limit of {} sitemap URLs for {}", self.config.sitemap_max_urls, host));
+                break;
+            }
+            if !fetched.insert(sitemap_url.to_string()) {
+                continue;
+            }
+            let sitemap = match self.fetch_xml(&sitemap_url, |xml| Some(sitemaps::parse_sitemap(xml))).await {
+                Some(sitemap) => sitemap,
+                None => continue,
+            };
+            tools::debug_log(self.config.debug, &format!("Found sitemap {} with {} entries", sitemap_url, sitemap.len()));
+            if let Err(e) = sqlite::insert_sitemap(&self.db_conn.lock().unwrap(), &sitemap_url, sitemap.kind(), sitemap.len()) {
+                tools::debug_log(self.config.debug, &format!("Failed to insert sitemap {} into SQLite: {}", sitemap_url, e));
+            }
+            let urls = match sitemap {
+                sitemaps::Sitemap::Index(children) => {
+                    if nesting < sitemaps::SITEMAP_MAX_NESTING {
+                        pending.extend(children.into_iter().rev().map(|child| (child, nesting + 1)));
+                    }
+                    continue;
+                }
+                sitemaps::Sitemap::UrlSet(urls) => urls,
+            };
+            let urls: Vec<data::SitemapUrl> = urls.into_iter().take(max_urls - found).collect();
+            found += urls.len();
+
+            // Filter the pages like any other links, then look their lastmod and priority back up.
+            let links = urls.iter().map(|url| url.url.to_string()).collect();
+            let hints: HashMap<String, data::SitemapUrl> = urls.into_iter()
+                .map(|url| (canonical::canonicalize(&self.config, url.url.as_str()), url))
+                .collect();
+            let allowed = tools::filter_links(&self.config, &self.client, links, &self.seen, &self.db_conn, &sitemap_url, &self.filters, entry.seed.as_ref(), true).await;
+            let referrer = canonical::canonicalize(&self.config, sitemap_url.as_str());
+            let conn = self.db_conn.lock().unwrap();
+            for url in allowed {
+                if !self.limits.try_claim(&url) {
+                    tools::debug_log(self.config.debug, &format!("Ignoring URL over its domain page budget: {}", url));
+                    continue;
                 }
+                let hint = hints.get(&canonical::canonicalize(&self.config, url.as_str()));
+                let lastmod = hint.and_then(|hint| hint.lastmod.clone());
+                let priority = hint.and_then(|hint| hint.priority);
+                if let Err(e) = sqlite::insert_frontier_url(&conn, &url, &referrer, entry.depth + 1, entry.seed.as_ref()) {
+                    tools::debug_log(self.config.debug, &format!("Failed to insert URL {} into the frontier: {}", url, e));
+                }
+                if let Err(e) = sqlite::set_frontier_sitemap(&conn, &url, lastmod.as_deref(), priority) {
+                    tools::debug_log(self.config.debug, &format!("Failed to record sitemap details for {}: {}", url, e));
+                }
+                next_entries.push(data::FrontierEntry::new(url, referrer.clone(), entry.depth + 1, entry.seed.clone()).with_sitemap(lastmod, priority));
+            }
+        }
+        next_entries
+    }
+
+    // Fetch the RSS and Atom feeds a page links to, store them, and queue their items one level below the page.
+    // Each feed is fetched once per run, and only if it's on a permitted domain. Items go through the same filters as links.
+    async fn crawl_feeds(&self, entry: &data::FrontierEntry, feed_links: Vec<String>, base_url: &Url, formatted_target_url: &String) -> Vec<data::FrontierEntry> {
+        let mut next_entries = Vec::new();
+        if !self.limits.within_depth(entry.depth + 1) {
+            return next_entries;
+        }
+        // Zero means there is no limit.
+        let max_items = if self.config.feed_max_items == 0 { usize::MAX } else { self.config.feed_max_items };
+        for link in feed_links {
+            let feed_url = match http::resolve_url(&self.config, &link, base_url) {
+                Some(feed_url) => feed_url,
+                None => continue,
+            };
+            if !self.filters.domains.is_allowed(&feed_url, entry.seed.as_ref()) {
+                tools::debug_log(self.config.debug, &format!("Ignoring feed on a domain that isn't permitted: {}", feed_url));
+                continue;
+            }
+            if !self.feeds.claim_feed(&canonical::canonicalize(&self.config, feed_url.as_str())) {
+                continue;
+            }
+            let feed = match self.fetch_xml(&feed_url, feeds::parse_feed).await {
+                Some(feed) => feed,
+                None => continue,
+            };
+            tools::debug_log(self.config.debug, &format!("Found {} feed {} with {} items", feed.kind, feed_url, feed.items.len()));
+
+            // Store the feed and its items, and remember each item's publication date.
+            let mut links = Vec::new();
+            let mut published: HashMap<String, Option<String>> = HashMap::new();
+            {
+                let conn = self.db_conn.lock().unwrap();
+                if let Err(e) = sqlite::insert_feed(&conn, &feed_url, formatted_target_url, &feed) {
+                    tools::debug_log(self.config.debug, &format!("Failed to insert feed {} into SQLite: {}", feed_url, e));
+                }
+                for item in feed.items.iter().take(max_items) {
+                    let item_url = match http::resolve_url(&self.config, &item.link, &feed_url) {
+                        Some(item_url) => item_url,
+                        None => continue,
+                    };
+                    let formatted_item_url = canonical::canonicalize(&self.config, item_url.as_str());
+                    if let Err(e) = sqlite::insert_feed_item(&conn, &feed_url, &formatted_item_url, item) {
+                        tools::debug_log(self.config.debug, &format!("Failed to insert feed item {} into SQLite: {}", item_url, e));
+                    }
+                    published.insert(formatted_item_url, item.published.clone());
+                    links.push(item_url.to_string());
+                }
+            }
+
+            let allowed = tools::filter_links(&self.config, &self.client, links, &self.seen, &self.db_conn, &feed_url, &self.filters, entry.seed.as_ref(), true).await;
+            let referrer = canonical::canonicalize(&self.config, feed_url.as_str());
+            let conn = self.db_conn.lock().unwrap();
+            for url in allowed {
+                if !self.limits.try_claim(&url) {
+                    tools::debug_log(self.config.debug, &format!("Ignoring URL over its domain page budget: {}", url));
+                    continue;
+                }
+                // The publication date is stored as the lastmod, so the priority strategy favours fresh items.
+                let lastmod = published.get(&canonical::canonicalize(&self.config, url.as_str())).cloned().flatten();
+                if let Err(e) = sqlite::insert_frontier_url(&conn, &url, &referrer, entry.depth + 1, entry.seed.as_ref()) {
+                    tools::debug_log(self.config.debug, &format!("Failed to insert URL {} into the frontier: {}", url, e));
+                }
+                if let Err(e) = sqlite::set_frontier_sitemap(&conn, &url, lastmod.as_deref(), None) {
+                    tools::debug_log(self.config.debug, &format!("Failed to record the publication date for {}: {}", url, e));
+                }
+                next_entries.push(data::FrontierEntry::new(url, referrer.clone(), entry.depth + 1, entry.seed.clone()).with_sitemap(lastmod, None));
+            }
+        }
+        next_entries
+    }
+
+    // Fetch a sitemap or feed and parse it on the blocking pool, waiting for the host's turn like any other request.
+    async fn fetch_xml<T: Send + 'static>(&self, url: &Url, parse: fn(&str) -> Option<T>) -> Option<T> {
+        let host = url.host_str().unwrap_or("");
+        if self.health.cooldown_remaining(host).is_some() {
+            return None;
+        }
+        self.politeness.wait_for_turn(&self.config, &self.client, &self.db_conn, url).await;
+        let result = {
+            let _permit = self.requests.acquire().await;
+            http::fetch_xml(&self.config, &self.client, &self.db_conn, url, sitemaps::SITEMAP_MAX_BYTES).await
+        };
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tools::debug_log(self.config.debug, &format!("Failed to fetch {}: {}", url, e));
+                return None;
+            }
+        };
+        let parsed = task::spawn_blocking(move || sitemaps::decode_xml(&bytes).map(|xml| parse(&xml))).await;
+        match parsed {
+            Ok(Ok(document)) => document,
+            Ok(Err(e)) => {
+                tools::debug_log(self.config.debug, &format!("{} from {}", e, url));
+                None
+            }
+            Err(e) => {
+                tools::debug_log(self.config.debug, &format!("Failed to parse {}: {}", url, e));
+                None
+            }
+        }
+    }
+
+    // Crawl until the queue is empty, or we've hit MAX_URLS_TO_VISIT.
+    // Pages are crawled concurrently, never more than MAX_CONCURRENT_REQUESTS at once.
+    async fn crawl_queue(self: &Arc<Self>) {
+        tools::debug_log(self.config.debug, &format!("Crawling {} queued URLs with the {} strategy.", self.queue.lock().unwrap().len(), self.config.crawl_strategy));
+        let max_in_flight = self.config.max_concurrent_requests.max(1);
+        let mut in_flight = JoinSet::new();
+        // Queued pages from hosts with a tripped circuit breaker, or that aren't due another request yet, are held here until the host is ready.
+        // That way a host with a long Crawl-delay never fills the slots other hosts could be using.
+        let mut paused: Vec<data::FrontierEntry> = Vec::new();
+        // Pages handed back by crawl_page, to be retried or to wait out their host's cool-down, are held here.
+        // They were already counted against MAX_URLS_TO_VISIT, so they start again without going through the queue.
+        let mut deferred: Vec<data::FrontierEntry> = Vec::new();
+        loop {
+            // Return paused pages to the queue once their host is ready again.
+            let (ready, still_paused): (Vec<_>, Vec<_>) = paused.into_iter()
+                .partition(|entry| self.time_until_ready(entry).is_zero());
+            paused = still_paused;
+            self.enqueue(ready);
+            let (ready, still_deferred): (Vec<_>, Vec<_>) = deferred.into_iter()
+                .partition(|entry| self.time_until_ready(entry).is_zero());
+            deferred = still_deferred;
+            for entry in ready {
+                if in_flight.len() >= max_in_flight {
+                    deferred.push(entry);
+                    continue;
+                }
+                let crawler = Arc::clone(self);
+                in_flight.spawn(async move { crawler.crawl_page(&entry).await });
+            }
+
+            // Start crawling queued pages, in the order chosen by the crawl strategy.
+            while in_flight.len() < max_in_flight
+                && data::URLS_VISITED.load(Ordering::SeqCst) < self.config.max_urls_to_visit {
+                let entry = match self.queue.lock().unwrap().pop() {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                if !self.time_until_ready(&entry).is_zero() {
+                    paused.push(entry);
+                    continue;
+                }
+                data::URLS_VISITED.fetch_add(1, Ordering::SeqCst);
+                let crawler = Arc::clone(self);
+                in_flight.spawn(async move { crawler.crawl_page(&entry).await });
             }
-        });
-    
-        // Check if we successfully crawled all of the child pages.
-        // If so, then we can mark this page as complete.
-        if success.lock().unwrap().clone() {
-            return true;
-        } 
-        // If we were not able to complete crawling this entire page and its child pages,
-        // then we need to let the recursive parent know by returning false.
-        false
-    }        
-
-    pub fn timed_crawl_website(&self, url: Url) {
+
+            // Wake up for the first paused or deferred page to be ready, as long as there's a free slot to start it in.
+            let wait = if in_flight.len() < max_in_flight {
+                paused.iter().chain(deferred.iter()).map(|entry| self.time_until_ready(entry)).min()
+            } else {
+                None
+            };
+
+            // If only paused and deferred pages are left, wait for the first of them.
+            // Once nothing is in flight or waiting, there's nothing left that could add to the queue.
+            if in_flight.is_empty() {
+                match wait {
+                    Some(wait) => {
+                        tools::debug_log(self.config.debug, &format!("Waiting {:?} for {} paused URLs.", wait, paused.len() + deferred.len()));
+                        tokio::time::sleep(wait).await;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+
+            // Wait for a page to finish, and queue the links it found, or for a paused page to be ready.
+            tokio::select! {
+                joined = in_flight.join_next() => match joined {
+                    Some(Ok(data::PageOutcome::Crawled(next_entries))) => self.enqueue(next_entries),
+                    Some(Ok(data::PageOutcome::Deferred(entry))) => deferred.push(*entry),
+                    Some(Err(e)) => tools::debug_log(self.config.debug, &format!("Crawler task failed: {}", e)),
+                    None => (),
+                },
+                _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => (),
+            }
+        }
+    }
+
+    // How long until a page can be fetched, because it's waiting to be retried, its host's circuit breaker tripped,
+    // or its host's next request slot is still reserved.
+    fn time_until_ready(&self, entry: &data::FrontierEntry) -> Duration {
+        let host = entry.url.host_str().unwrap_or("");
+        let retry_wait = entry.not_before.map(|not_before| not_before.saturating_duration_since(Instant::now())).unwrap_or_default();
+        let cooldown = self.health.cooldown_remaining(host).unwrap_or_default();
+        retry_wait.max(cooldown).max(self.politeness.time_until_turn(host))
+    }
+
+    // Get the hosts that tripped their circuit breaker, and how many times.
+    pub fn tripped_domains(&self) -> Vec<(String, u32)> {
+        self.health.tripped_domains()
+    }
+
+    fn enqueue(&self, entries: Vec<data::FrontierEntry>) {
+        let mut queue = self.queue.lock().unwrap();
+        for entry in entries {
+            queue.push(entry);
+        }
+    }
+
+    // Crawl from each seed. Everything found from a seed remembers it, so SEED_SCOPE can keep it on the seed's site.
+    pub async fn timed_crawl_website(self: &Arc<Self>, seeds: Vec<Url>) {
         let start = Local::now();
-        self.crawl_website_dfs(&url, &"STARTING_URL".to_string());
+        let referrer_url = "STARTING_URL".to_string();
+        let mut entries = Vec::new();
+        for seed in seeds {
+            // Seeds go through the same domain, URL rule, spider trap and robots.txt checks as links.
+            let accepted = tools::filter_links(&self.config, &self.client, vec![seed.to_string()], &self.seen, &self.db_conn, &seed, &self.filters, Some(&seed), true).await;
+            if accepted.is_empty() {
+                tools::debug_log(self.config.debug, &format!("Skipping seed {}, it was rejected by the crawl filters", seed));
+                continue;
+            }
+            if !self.limits.try_claim(&seed) {
+                tools::debug_log(self.config.debug, &format!("Skipping seed {}, it's over its domain page budget", seed));
+                continue;
+            }
+            if let Err(e) = sqlite::insert_frontier_url(&self.db_conn.lock().unwrap(), &seed, &referrer_url, 0, Some(&seed)) {
+                tools::debug_log(self.config.debug, &format!("Failed to insert URL {} into the frontier: {}", seed, e));
+            }
+            entries.push(data::FrontierEntry::new(seed.clone(), referrer_url.clone(), 0, Some(seed)));
+        }
+        self.enqueue(entries);
+        self.crawl_queue().await;
         let duration: chrono::Duration = Local::now().signed_duration_since(start);
         println!("Time elapsed in crawl_website() is: {:?}", duration);
     }
-}
\ No newline at end of file
+
+    // Pick up an interrupted crawl from the frontier stored in the database.
+    // If there is nothing left in the frontier, start again from the given seeds.
+    pub async fn timed_resume_crawl(self: &Arc<Self>, seeds: Vec<Url>) {
+        let (seen, pending, domain_pages) = {
+            let conn = self.db_conn.lock().unwrap();
+            let seen = sqlite::get_seen_urls(&conn, &self.config).unwrap_or_else(|e| {
+                tools::debug_log(self.config.debug, &format!("Failed to load seen URLs from SQLite: {}", e));
+                Vec::new()
+            });
+            let pending = sqlite::get_pending_frontier(&conn).unwrap_or_else(|e| {
+                tools::debug_log(self.config.debug, &format!("Failed to load the frontier from SQLite: {}", e));
+                Vec::new()
+            });
+            let domain_pages = sqlite::get_domain_page_counts(&conn, &self.config).unwrap_or_else(|e| {
+                tools::debug_log(self.config.debug, &format!("Failed to load domain page counts from SQLite: {}", e));
+                Vec::new()
+            });
+            (seen, pending, domain_pages)
+        };
+        if pending.is_empty() {
+            tools::debug_log(self.config.debug, "Nothing to resume in the frontier, starting a new crawl.");
+            self.timed_crawl_website(seeds).await;
+            return;
+        }
+        tools::debug_log(self.config.debug, &format!("Resuming crawl with {} queued URLs and {} seen URLs.", pending.len(), seen.len()));
+        self.seen.lock().unwrap().extend(seen);
+
+        // Pages visited or still queued from the last run count against their domain budgets.
+        for (domain, pages) in domain_pages {
+            self.limits.add_claimed(&domain, pages);
+        }
+
+        let start = Local::now();
+        self.enqueue(pending);
+        self.crawl_queue().await;
+        let duration: chrono::Duration = Local::now().signed_duration_since(start);
+        println!("Time elapsed in resume_crawl() is: {:?}", duration);
+    }
+
+    fn set_frontier_state(&self, conn: &Connection, url: &Url, state: data::FrontierState) {
+        if let Err(e) = sqlite::set_frontier_state(conn, url, state) {
+            tools::debug_log(self.config.debug, &format!("Failed to update frontier state for {}: {}", url, e));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Local};
 use reqwest::Url;
@@ -8,10 +9,12 @@ pub(crate) struct VisitedSite {
     url: String,
     referrer: String,
     visited_at: DateTime<Local>,
+    depth: usize,
+    domain: Option<String>,
 }
 impl VisitedSite {
-    pub fn new(url: String, referrer: String, visited_at: DateTime<Local>) -> Self {
-        Self { url, referrer, visited_at }
+    pub fn new(url: String, referrer: String, visited_at: DateTime<Local>, depth: usize, domain: Option<String>) -> Self {
+        Self { url, referrer, visited_at, depth, domain }
     }
     pub fn url(&self) -> &String {
         &self.url
@@ -22,6 +25,12 @@ impl VisitedSite {
     pub fn visited_at(&self) -> &DateTime<Local> {
         &self.visited_at
     }
+    pub fn depth(&self) -> &usize {
+        &self.depth
+    }
+    pub fn domain(&self) -> &Option<String> {
+        &self.domain
+    }
 }
 pub(crate) static URLS_VISITED: AtomicUsize = AtomicUsize::new(0);
 
@@ -37,4 +46,149 @@ pub(crate) struct SiteUrls {
 pub(crate) struct SiteLinks {
     pub(crate) link_links: Vec<String>,
     pub(crate) img_links: Vec<String>,
-}
\ No newline at end of file
+    // The href of the page's <base> element, if it has one.
+    pub(crate) base_href: Option<String>,
+    // The href of the page's <link rel="canonical">, if it has one.
+    pub(crate) canonical_href: Option<String>,
+    // RSS and Atom feeds the page links to.
+    pub(crate) feed_links: Vec<String>,
+    // Links only ever marked rel="nofollow", "ugc" or "sponsored" on the page.
+    pub(crate) nofollow_links: Vec<String>,
+    // The name and content of each <meta name> tag, for its robots directives.
+    pub(crate) meta_tags: Vec<(String, String)>,
+}
+
+// The lifecycle of a URL in the persistent crawl frontier.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FrontierState {
+    Queued,
+    Fetching,
+    Fetched,
+    Failed,
+}
+impl FrontierState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrontierState::Queued => "queued",
+            FrontierState::Fetching => "fetching",
+            FrontierState::Fetched => "fetched",
+            FrontierState::Failed => "failed",
+        }
+    }
+}
+
+// A URL waiting to be crawled, along with where we found it and the seed it was reached from.
+// URLs found in a sitemap also carry its lastmod and priority, for the priority strategy.
+#[derive(Clone)]
+pub(crate) struct FrontierEntry {
+    pub(crate) url: Url,
+    pub(crate) referrer: String,
+    pub(crate) depth: usize,
+    pub(crate) seed: Option<Url>,
+    pub(crate) lastmod: Option<String>,
+    pub(crate) sitemap_priority: Option<f64>,
+    // Which fetch attempt is next, counting from 1, and when a retried page can be fetched again.
+    pub(crate) attempt: u32,
+    pub(crate) not_before: Option<Instant>,
+}
+impl FrontierEntry {
+    pub fn new(url: Url, referrer: String, depth: usize, seed: Option<Url>) -> Self {
+        Self { url, referrer, depth, seed, lastmod: None, sitemap_priority: None, attempt: 1, not_before: None }
+    }
+
+    pub fn with_sitemap(mut self, lastmod: Option<String>, sitemap_priority: Option<f64>) -> Self {
+        self.lastmod = lastmod;
+        self.sitemap_priority = sitemap_priority;
+        self
+    }
+
+    // The same page, as the next attempt after a failure, to be fetched once the delay has passed.
+    pub fn retry_in(mut self, delay: Duration) -> Self {
+        self.attempt += 1;
+        self.not_before = Some(Instant::now() + delay);
+        self
+    }
+}
+
+// What crawling a page left to do.
+pub(crate) enum PageOutcome {
+    // The page is finished with, and these are the links it found.
+    Crawled(Vec<FrontierEntry>),
+    // The page has to be fetched again later, so it waits outside the queue without holding a slot.
+    Deferred(Box<FrontierEntry>),
+}
+
+// A page listed in a sitemap.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SitemapUrl {
+    pub(crate) url: Url,
+    pub(crate) lastmod: Option<String>,
+    pub(crate) priority: Option<f64>,
+}
+
+// A robots.txt stored by an earlier fetch, and how many seconds ago it was fetched.
+pub(crate) struct StoredRobotsTxt {
+    pub(crate) status_code: Option<u16>,
+    pub(crate) body: String,
+    pub(crate) age_secs: u64,
+}
+
+// The main content of a page as plain text, ready to be stored in page_text.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PageText {
+    pub(crate) title: Option<String>,
+    pub(crate) text: String,
+    pub(crate) word_count: usize,
+}
+
+// How far the crawl got from a single seed.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SeedStats {
+    pub(crate) seed: String,
+    pub(crate) discovered: u64,
+    pub(crate) fetched: u64,
+    pub(crate) failed: u64,
+    pub(crate) pending: u64,
+}
+
+// What we learned about a single HTTP request, successful or not.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseInfo {
+    pub(crate) url: Url,
+    pub(crate) final_url: Option<Url>,
+    pub(crate) status_code: Option<u16>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_length: Option<u64>,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) ttfb: Duration,
+    pub(crate) duration: Duration,
+    pub(crate) error: Option<String>,
+    pub(crate) retry_after: Option<Duration>,
+    // The target of a Link: <...>; rel="canonical" header, if there was one.
+    pub(crate) canonical_link: Option<String>,
+    // The values of any X-Robots-Tag headers.
+    pub(crate) robots_tags: Vec<String>,
+    pub(crate) attempt: u32,
+}
+impl ResponseInfo {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            final_url: None,
+            status_code: None,
+            content_type: None,
+            content_length: None,
+            headers: Vec::new(),
+            ttfb: Duration::ZERO,
+            duration: Duration::ZERO,
+            error: None,
+            retry_after: None,
+            canonical_link: None,
+            robots_tags: Vec::new(),
+            attempt: 1,
+        }
+    }
+    pub fn is_success(&self) -> bool {
+        matches!(self.status_code, Some(status) if (200..300).contains(&status))
+    }
+}
@@ -0,0 +1,291 @@
+use std::net::IpAddr;
+use lazy_static::lazy_static;
+use publicsuffix::{List, Psl};
+use reqwest::Url;
+use url::Host;
+
+use crate::config;
+use crate::tools;
+
+lazy_static! {
+    // A snapshot of https://publicsuffix.org/list/public_suffix_list.dat, parsed once on first use.
+    static ref PUBLIC_SUFFIX_LIST: List = include_str!("../assets/public_suffix_list.dat").parse().expect("Failed to parse the public suffix list");
+}
+
+// Get the registrable domain of a host, the public suffix plus one label, such as example.co.uk for www.example.co.uk.
+// Hosts without one, like localhost, are their own registrable domain.
+pub(crate) fn registrable_domain(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    PUBLIC_SUFFIX_LIST.domain(host.as_bytes())
+        .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok().map(|d| d.to_string()))
+        .unwrap_or(host)
+}
+
+// A single entry in PERMITTED_DOMAINS or BLACKLIST_DOMAINS.
+#[derive(Clone, Debug, PartialEq)]
+enum DomainRule {
+    // example.com matches only that host.
+    Exact(String),
+    // *.example.com matches example.com and every subdomain of it.
+    Subdomains(String),
+    // site:example.com matches every host with the same registrable domain. site:seed is expanded to every seed's.
+    Site(String),
+    // An IP address, or a CIDR range like 10.0.0.0/8.
+    Ip(IpAddr, u8),
+}
+
+impl DomainRule {
+    fn parse(rule: &str) -> Option<Self> {
+        let rule = rule.trim().trim_end_matches('.').to_lowercase();
+        if rule.is_empty() {
+            return None;
+        }
+        if let Some(site) = rule.strip_prefix("site:") {
+            return Some(DomainRule::Site(registrable_domain(site)));
+        }
+        if let Some(domain) = rule.strip_prefix("*.") {
+            return Some(DomainRule::Subdomains(domain.to_string()));
+        }
+        let (address, prefix) = match rule.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (rule.as_str(), None),
+        };
+        if let Ok(ip) = address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= max_prefix)?,
+                None => max_prefix,
+            };
+            return Some(DomainRule::Ip(ip, prefix));
+        }
+        Some(DomainRule::Exact(rule))
+    }
+
+    fn matches(&self, host: &Host<&str>) -> bool {
+        match (self, host) {
+            (DomainRule::Exact(domain), Host::Domain(host)) => host == domain,
+            (DomainRule::Subdomains(domain), Host::Domain(host)) => {
+                host == domain || host.strip_suffix(domain.as_str()).is_some_and(|prefix| prefix.ends_with('.'))
+            }
+            (DomainRule::Site(site), Host::Domain(host)) => registrable_domain(host) == *site,
+            (DomainRule::Ip(network, prefix), Host::Ipv4(ip)) => in_network(&IpAddr::V4(*ip), network, *prefix),
+            (DomainRule::Ip(network, prefix), Host::Ipv6(ip)) => in_network(&IpAddr::V6(*ip), network, *prefix),
+            _ => false,
+        }
+    }
+}
+
+// Check if an address falls in network/prefix.
+fn in_network(ip: &IpAddr, network: &IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(*network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(*network) & mask
+        }
+        _ => false,
+    }
+}
+
+// How far a crawl may stray from the seed it started at, set by SEED_SCOPE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SeedScope {
+    // Follow PERMITTED_DOMAINS and FREE_CRAWL as usual.
+    None,
+    // Stay on the seed's host.
+    Host,
+    // Stay on the seed's registrable domain, including its other subdomains.
+    Domain,
+}
+
+impl SeedScope {
+    fn parse(scope: &str) -> Option<Self> {
+        match scope.trim().to_lowercase().as_str() {
+            "none" | "" => Some(SeedScope::None),
+            "host" => Some(SeedScope::Host),
+            "domain" => Some(SeedScope::Domain),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, seed: &Url, host: &Host<&str>) -> bool {
+        let seed_host = match seed.host() {
+            Some(seed_host) => seed_host,
+            None => return false,
+        };
+        match (self, &seed_host, host) {
+            (SeedScope::Domain, Host::Domain(seed_host), Host::Domain(host)) => registrable_domain(seed_host) == registrable_domain(host),
+            _ => seed_host.to_string().eq_ignore_ascii_case(&host.to_string()),
+        }
+    }
+}
+
+// The permit list and blacklist, parsed once from the config.
+pub(crate) struct DomainRules {
+    free_crawl: bool,
+    permitted: Vec<DomainRule>,
+    blacklist: Vec<DomainRule>,
+    seed_scope: SeedScope,
+}
+
+impl DomainRules {
+    pub fn new(config: &config::Config, seeds: &[Url]) -> Self {
+        let parse = |rules: &Vec<String>| {
+            let mut parsed: Vec<DomainRule> = Vec::new();
+            for rule in rules.iter() {
+                // site:seed stands for the registrable domain of every seed, or the address of a seed that's an IP.
+                if rule.trim().eq_ignore_ascii_case("site:seed") {
+                    for seed in seeds.iter() {
+                        let seed_rule = match seed.host() {
+                            Some(Host::Domain(host)) => DomainRule::parse(&format!("site:{}", host)),
+                            Some(host) => DomainRule::parse(&host.to_string()),
+                            None => None,
+                        };
+                        if let Some(seed_rule) = seed_rule.filter(|seed_rule| !parsed.contains(seed_rule)) {
+                            parsed.push(seed_rule);
+                        }
+                    }
+                    continue;
+                }
+                match DomainRule::parse(rule) {
+                    Some(rule) => parsed.push(rule),
+                    None => tools::debug_log(config.debug, &format!("Ignoring invalid domain rule {}", rule)),
+                }
+            }
+            parsed
+        };
+        let seed_scope = SeedScope::parse(&config.seed_scope).unwrap_or_else(|| {
+            tools::debug_log(config.debug, &format!("Ignoring unknown seed scope {}", config.seed_scope));
+            SeedScope::None
+        });
+        DomainRules {
+            free_crawl: config.free_crawl,
+            permitted: parse(&config.permitted_domains),
+            blacklist: parse(&config.blacklist_domains),
+            seed_scope,
+        }
+    }
+
+    // Check if the URL's host is permitted and not blacklisted.
+    // When SEED_SCOPE is set, a URL reached from a seed is permitted only if it's in that seed's scope.
+    pub fn is_allowed(&self, url: &Url, seed: Option<&Url>) -> bool {
+        let host = match url.host() {
+            Some(host) => host,
+            None => return false,
+        };
+        let permitted = match seed {
+            Some(seed) if self.seed_scope != SeedScope::None => self.seed_scope.contains(seed, &host),
+            _ => self.free_crawl || self.permitted.iter().any(|rule| rule.matches(&host)),
+        };
+        permitted && !self.blacklist.iter().any(|rule| rule.matches(&host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(permitted: &[&str], blacklist: &[&str]) -> DomainRules {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.free_crawl = false;
+        config.permitted_domains = permitted.iter().map(|d| d.to_string()).collect();
+        config.blacklist_domains = blacklist.iter().map(|d| d.to_string()).collect();
+        DomainRules::new(&config, &[Url::parse("https://www.seed.co.uk/start").unwrap()])
+    }
+
+    fn allowed(rules: &DomainRules, url: &str) -> bool {
+        rules.is_allowed(&Url::parse(url).unwrap(), None)
+    }
+
+    #[test]
+    fn test_public_suffix_list_is_complete() {
+        let list = include_str!("../assets/public_suffix_list.dat");
+        assert!(list.contains("===BEGIN ICANN DOMAINS===") && list.contains("===END ICANN DOMAINS==="));
+        assert!(list.contains("===BEGIN PRIVATE DOMAINS===") && list.contains("===END PRIVATE DOMAINS==="));
+        assert_eq!(PUBLIC_SUFFIX_LIST.suffix(b"www.example.co.uk").map(|suffix| suffix.as_bytes().to_vec()), Some(b"co.uk".to_vec()));
+    }
+
+    #[test]
+    fn test_registrable_domain() {
+        assert_eq!(registrable_domain("www.example.com"), "example.com");
+        assert_eq!(registrable_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(registrable_domain("user.github.io"), "user.github.io");
+        assert_eq!(registrable_domain("localhost"), "localhost");
+    }
+
+    #[test]
+    fn test_exact_and_wildcard_rules() {
+        let rules = rules(&["example.com", "*.example.org"], &["*.ads.example.org"]);
+        assert!(allowed(&rules, "https://example.com/"));
+        assert!(!allowed(&rules, "https://www.example.com/"));
+        assert!(allowed(&rules, "https://example.org/"));
+        assert!(allowed(&rules, "https://blog.www.example.org/"));
+        assert!(!allowed(&rules, "https://notexample.org/"));
+        assert!(!allowed(&rules, "https://ads.example.org/"));
+        assert!(!allowed(&rules, "https://cdn.ads.example.org/"));
+    }
+
+    #[test]
+    fn test_registrable_domain_rules() {
+        let rules = rules(&["site:seed", "site:www.example.com"], &["site:blocked.co.uk"]);
+        assert!(allowed(&rules, "https://seed.co.uk/"));
+        assert!(allowed(&rules, "https://blog.seed.co.uk/"));
+        assert!(!allowed(&rules, "https://other.co.uk/"));
+        assert!(allowed(&rules, "https://shop.example.com/"));
+        assert!(!allowed(&rules, "https://www.blocked.co.uk/"));
+    }
+
+    #[test]
+    fn test_site_seed_with_several_seeds() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.free_crawl = false;
+        config.permitted_domains = vec!["site:seed".to_string()];
+        let seeds: Vec<Url> = ["https://www.seed.co.uk/", "https://blog.seed.co.uk/", "https://news.example.org/", "http://127.0.0.1:8080/"]
+            .iter().map(|seed| Url::parse(seed).unwrap()).collect();
+        let rules = DomainRules::new(&config, &seeds);
+        assert_eq!(rules.permitted.len(), 3);
+        assert!(allowed(&rules, "https://shop.seed.co.uk/"));
+        assert!(allowed(&rules, "https://example.org/"));
+        assert!(allowed(&rules, "http://127.0.0.1:9000/"));
+        assert!(!allowed(&rules, "https://other.co.uk/"));
+        assert!(!allowed(&rules, "http://127.0.0.2/"));
+    }
+
+    #[test]
+    fn test_ip_rules() {
+        let rules = rules(&["127.0.0.1", "10.0.0.0/8", "[::1]"], &["10.0.0.99"]);
+        assert!(allowed(&rules, "http://127.0.0.1:8080/"));
+        assert!(allowed(&rules, "http://10.1.2.3/"));
+        assert!(!allowed(&rules, "http://10.0.0.99/"));
+        assert!(!allowed(&rules, "http://192.168.0.1/"));
+        assert!(allowed(&rules, "http://[::1]/"));
+        assert!(!allowed(&rules, "http://[::2]/"));
+    }
+
+    #[test]
+    fn test_seed_scope() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.free_crawl = true;
+        config.blacklist_domains = vec!["shop.seed.co.uk".to_string()];
+        let seed = Url::parse("https://www.seed.co.uk/").unwrap();
+        let ip_seed = Url::parse("http://127.0.0.1:8080/").unwrap();
+        let in_scope = |rules: &DomainRules, url: &str, seed: &Url| rules.is_allowed(&Url::parse(url).unwrap(), Some(seed));
+
+        config.seed_scope = "host".to_string();
+        let rules = DomainRules::new(&config, &[]);
+        assert!(in_scope(&rules, "https://www.seed.co.uk/page", &seed));
+        assert!(!in_scope(&rules, "https://blog.seed.co.uk/", &seed));
+        assert!(in_scope(&rules, "http://127.0.0.1:9090/", &ip_seed));
+        assert!(allowed(&rules, "https://other.com/"));
+
+        config.seed_scope = "domain".to_string();
+        let rules = DomainRules::new(&config, &[]);
+        assert!(in_scope(&rules, "https://blog.seed.co.uk/", &seed));
+        assert!(!in_scope(&rules, "https://shop.seed.co.uk/", &seed));
+        assert!(!in_scope(&rules, "https://other.co.uk/", &seed));
+        assert!(!in_scope(&rules, "http://127.0.0.2/", &ip_seed));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use chrono::DateTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::sitemaps;
+
+lazy_static! {
+    static ref RSS_ROOT: Regex = Regex::new(r"(?i)<(?:rss|rdf:RDF)[\s>]").unwrap();
+    static ref ATOM_ROOT: Regex = Regex::new(r"(?i)<feed[\s>]").unwrap();
+    static ref FEED_ITEM: Regex = Regex::new(r"(?is)<(item|entry)\b[^>]*>(.*?)</(?:item|entry)>").unwrap();
+    static ref FEED_TITLE: Regex = Regex::new(r"(?is)<title\b[^>]*>(.*?)</title>").unwrap();
+    static ref RSS_LINK: Regex = Regex::new(r"(?is)<link\b[^>]*>(.*?)</link>").unwrap();
+    static ref RSS_GUID: Regex = Regex::new(r"(?is)<guid\b([^>]*)>(.*?)</guid>").unwrap();
+    static ref RSS_DATE: Regex = Regex::new(r"(?is)<(?:pubDate|dc:date)\b[^>]*>(.*?)</(?:pubDate|dc:date)>").unwrap();
+    static ref ATOM_LINK: Regex = Regex::new(r"(?is)<link\b([^>]*?)/?>").unwrap();
+    static ref ATOM_PUBLISHED: Regex = Regex::new(r"(?is)<published\b[^>]*>(.*?)</published>").unwrap();
+    static ref ATOM_UPDATED: Regex = Regex::new(r"(?is)<updated\b[^>]*>(.*?)</updated>").unwrap();
+    static ref XML_ATTRIBUTE: Regex = Regex::new(r#"([\w:]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+// A parsed RSS or Atom feed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Feed {
+    pub(crate) kind: &'static str,
+    pub(crate) title: Option<String>,
+    pub(crate) items: Vec<FeedItem>,
+}
+
+// A single item or entry in a feed. The link may be relative to the feed.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FeedItem {
+    pub(crate) link: String,
+    pub(crate) title: Option<String>,
+    // The publication date, as RFC 3339 if it could be parsed, or as the feed gave it if not.
+    pub(crate) published: Option<String>,
+}
+
+// Remembers which feeds we've already fetched, so a feed linked from every page is only fetched once per run.
+pub(crate) struct FeedDiscovery {
+    feeds: Mutex<HashSet<String>>,
+}
+
+impl FeedDiscovery {
+    pub fn new() -> Self {
+        FeedDiscovery { feeds: Mutex::new(HashSet::new()) }
+    }
+
+    // Check if this feed still needs fetching, and claim it if so.
+    pub fn claim_feed(&self, feed_url: &str) -> bool {
+        self.feeds.lock().unwrap().insert(feed_url.to_string())
+    }
+}
+
+// Parse an RSS 2.0, RSS 1.0 (RDF) or Atom feed. Returns None if the document isn't a feed.
+// Items without a link are skipped.
+pub(crate) fn parse_feed(xml: &str) -> Option<Feed> {
+    let kind = if RSS_ROOT.is_match(xml) {
+        "rss"
+    } else if ATOM_ROOT.is_match(xml) {
+        "atom"
+    } else {
+        return None;
+    };
+
+    // The feed's own title comes before its first item.
+    let first_item = FEED_ITEM.find(xml).map(|item| item.start()).unwrap_or(xml.len());
+    let title = sitemaps::tag_value(&FEED_TITLE, &xml[..first_item]).filter(|title| !title.is_empty());
+
+    let mut items = Vec::new();
+    for item in FEED_ITEM.captures_iter(xml) {
+        let block = &item[2];
+        let (link, published) = if item[1].eq_ignore_ascii_case("entry") {
+            let published = sitemaps::tag_value(&ATOM_PUBLISHED, block).or_else(|| sitemaps::tag_value(&ATOM_UPDATED, block));
+            (atom_link(block), published)
+        } else {
+            (rss_link(block), sitemaps::tag_value(&RSS_DATE, block))
+        };
+        let link = match link {
+            Some(link) => link,
+            None => continue,
+        };
+        items.push(FeedItem {
+            link,
+            title: sitemaps::tag_value(&FEED_TITLE, block).filter(|title| !title.is_empty()),
+            published: published.filter(|date| !date.is_empty()).map(|date| normalize_date(&date)),
+        });
+    }
+    Some(Feed { kind, title, items })
+}
+
+// An RSS item links to its article with <link>, or with a <guid> that's a permalink.
+fn rss_link(block: &str) -> Option<String> {
+    if let Some(link) = sitemaps::tag_value(&RSS_LINK, block).filter(|link| !link.is_empty()) {
+        return Some(link);
+    }
+    let guid = RSS_GUID.captures(block)?;
+    let is_permalink = !attributes(&guid[1]).iter().any(|(name, value)| name.eq_ignore_ascii_case("ispermalink") && value.eq_ignore_ascii_case("false"));
+    let value = sitemaps::xml_text(&guid[2]);
+    (is_permalink && value.starts_with("http")).then_some(value)
+}
+
+// An Atom entry links to its article with a <link> whose rel is alternate, or missing.
+fn atom_link(block: &str) -> Option<String> {
+    ATOM_LINK.captures_iter(block).find_map(|link| {
+        let attributes = attributes(&link[1]);
+        let rel = attributes.iter().find(|(name, _)| name == "rel").map(|(_, value)| value.as_str()).unwrap_or("alternate");
+        if rel != "alternate" {
+            return None;
+        }
+        attributes.into_iter().find(|(name, _)| name == "href").map(|(_, href)| href.replace("&amp;", "&"))
+    })
+}
+
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    XML_ATTRIBUTE.captures_iter(tag).map(|attribute| {
+        let value = attribute.get(2).or(attribute.get(3)).map(|value| value.as_str()).unwrap_or("");
+        (attribute[1].to_lowercase(), value.to_string())
+    }).collect()
+}
+
+// RSS dates are RFC 2822 and Atom dates are RFC 3339. Store both as RFC 3339, so they sort and compare the same.
+fn normalize_date(date: &str) -> String {
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss() {
+        let xml = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+              <title>Example News</title><link>https://www.example.com/</link>
+              <item><title>First &amp; best</title><link>https://www.example.com/a?x=1&amp;y=2</link><pubDate>Tue, 10 Jun 2025 04:00:00 GMT</pubDate></item>
+              <item><title><![CDATA[Second]]></title><guid isPermaLink="true">https://www.example.com/b</guid></item>
+              <item><title>No link</title><guid isPermaLink="false">https://www.example.com/c</guid></item>
+            </channel></rss>"#;
+        let feed = parse_feed(xml).unwrap();
+        assert_eq!(feed.kind, "rss");
+        assert_eq!(feed.title.as_deref(), Some("Example News"));
+        assert_eq!(feed.items, vec![
+            FeedItem { link: "https://www.example.com/a?x=1&y=2".to_string(), title: Some("First & best".to_string()), published: Some("2025-06-10T04:00:00+00:00".to_string()) },
+            FeedItem { link: "https://www.example.com/b".to_string(), title: Some("Second".to_string()), published: None },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Example Blog</title>
+              <link rel="self" href="https://www.example.com/feed.atom"/>
+              <entry><title>Post</title><link rel="edit" href="/edit/1"/><link href="/posts/1"/><published>2025-06-10T04:00:00Z</published><updated>2025-06-11T04:00:00Z</updated></entry>
+              <entry><title>Updated</title><link rel='alternate' type='text/html' href='https://www.example.com/posts/2'/><updated>2025-06-12T04:00:00+02:00</updated></entry>
+            </feed>"#;
+        let feed = parse_feed(xml).unwrap();
+        assert_eq!(feed.kind, "atom");
+        assert_eq!(feed.title.as_deref(), Some("Example Blog"));
+        assert_eq!(feed.items, vec![
+            FeedItem { link: "/posts/1".to_string(), title: Some("Post".to_string()), published: Some("2025-06-10T04:00:00+00:00".to_string()) },
+            FeedItem { link: "https://www.example.com/posts/2".to_string(), title: Some("Updated".to_string()), published: Some("2025-06-12T04:00:00+02:00".to_string()) },
+        ]);
+        assert_eq!(parse_feed("<html><body>Not a feed</body></html>"), None);
+    }
+}
@@ -0,0 +1,291 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::Mutex;
+use chrono::Utc;
+use regex::Regex;
+use reqwest::Url;
+
+use crate::config;
+use crate::data;
+use crate::sitemaps;
+use crate::tools;
+
+// Score weights for the priority strategy. Every link starts at zero, loses a point per level of depth,
+// and gains a bonus for each priority domain or URL pattern it matches.
+// URLs from a sitemap gain up to PRIORITY_SITEMAP_BONUS from their <priority>, and a bonus if their <lastmod> is recent.
+const PRIORITY_DEPTH_PENALTY: i64 = 1;
+const PRIORITY_DOMAIN_BONUS: i64 = 10;
+const PRIORITY_PATTERN_BONUS: i64 = 5;
+const PRIORITY_SITEMAP_BONUS: f64 = 10.0;
+const PRIORITY_FRESH_BONUS: i64 = 5;
+const PRIORITY_FRESH_DAYS: i64 = 7;
+
+// The order in which queued URLs are handed to the crawler threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CrawlStrategy {
+    Bfs,
+    Dfs,
+    Priority,
+}
+impl CrawlStrategy {
+    pub fn parse(strategy: &str) -> Option<Self> {
+        match strategy.trim().to_lowercase().as_str() {
+            "bfs" => Some(CrawlStrategy::Bfs),
+            "dfs" => Some(CrawlStrategy::Dfs),
+            "priority" => Some(CrawlStrategy::Priority),
+            _ => None,
+        }
+    }
+}
+
+// A queued entry in the priority heap. Higher scores pop first, ties pop in the order they were queued.
+struct ScoredEntry {
+    score: i64,
+    sequence: Reverse<u64>,
+    entry: data::FrontierEntry,
+}
+impl PartialEq for ScoredEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score && self.sequence == other.sequence
+    }
+}
+impl Eq for ScoredEntry {}
+impl PartialOrd for ScoredEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ScoredEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.score, self.sequence).cmp(&(other.score, other.sequence))
+    }
+}
+
+// The in-memory work queue that drives the crawl.
+pub(crate) struct CrawlQueue {
+    strategy: CrawlStrategy,
+    entries: VecDeque<data::FrontierEntry>,
+    scored: BinaryHeap<ScoredEntry>,
+    sequence: u64,
+    priority_domains: Vec<String>,
+    priority_patterns: Vec<Regex>,
+}
+
+impl CrawlQueue {
+    pub fn new(config: &config::Config) -> Self {
+        let strategy = CrawlStrategy::parse(&config.crawl_strategy).unwrap_or_else(|| {
+            tools::debug_log(config.debug, &format!("Unknown crawl strategy {}, using bfs.", config.crawl_strategy));
+            CrawlStrategy::Bfs
+        });
+        let priority_patterns = config.priority_patterns.iter().filter_map(|pattern| {
+            match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    tools::debug_log(config.debug, &format!("Ignoring invalid priority pattern {}: {}", pattern, e));
+                    None
+                }
+            }
+        }).collect();
+        CrawlQueue {
+            strategy,
+            entries: VecDeque::new(),
+            scored: BinaryHeap::new(),
+            sequence: 0,
+            priority_domains: config.priority_domains.clone(),
+            priority_patterns,
+        }
+    }
+
+    pub fn push(&mut self, entry: data::FrontierEntry) {
+        match self.strategy {
+            CrawlStrategy::Bfs | CrawlStrategy::Dfs => self.entries.push_back(entry),
+            CrawlStrategy::Priority => {
+                let score = self.score(&entry);
+                self.sequence += 1;
+                self.scored.push(ScoredEntry { score, sequence: Reverse(self.sequence), entry });
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<data::FrontierEntry> {
+        match self.strategy {
+            CrawlStrategy::Bfs => self.entries.pop_front(),
+            CrawlStrategy::Dfs => self.entries.pop_back(),
+            CrawlStrategy::Priority => self.scored.pop().map(|scored| scored.entry),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.scored.len()
+    }
+
+    // Score a URL for the priority strategy.
+    fn score(&self, entry: &data::FrontierEntry) -> i64 {
+        let mut score = -(entry.depth as i64) * PRIORITY_DEPTH_PENALTY;
+        if let Some(domain) = entry.url.domain() {
+            if self.priority_domains.iter().any(|d| domain.eq(d)) {
+                score += PRIORITY_DOMAIN_BONUS;
+            }
+        }
+        let matches = self.priority_patterns.iter().filter(|re| re.is_match(entry.url.as_str())).count();
+        score += matches as i64 * PRIORITY_PATTERN_BONUS;
+        if let Some(priority) = entry.sitemap_priority {
+            score += (priority * PRIORITY_SITEMAP_BONUS).round() as i64;
+        }
+        if let Some(lastmod) = entry.lastmod.as_deref().and_then(sitemaps::parse_lastmod) {
+            if Utc::now().signed_duration_since(lastmod) <= chrono::Duration::days(PRIORITY_FRESH_DAYS) {
+                score += PRIORITY_FRESH_BONUS;
+            }
+        }
+        score
+    }
+}
+
+// Enforces MAX_DEPTH from the starting URL, and the page budget for each domain.
+pub(crate) struct CrawlLimits {
+    max_depth: usize,
+    max_pages_per_domain: usize,
+    domain_page_budgets: HashMap<String, usize>,
+    domain_pages: Mutex<HashMap<String, usize>>,
+}
+
+impl CrawlLimits {
+    pub fn new(config: &config::Config) -> Self {
+        CrawlLimits {
+            max_depth: config.max_depth,
+            max_pages_per_domain: config.max_pages_per_domain,
+            domain_page_budgets: config.domain_page_budgets.clone(),
+            domain_pages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Check if links found at this depth can still be followed. Zero means there is no limit.
+    pub fn within_depth(&self, depth: usize) -> bool {
+        self.max_depth == 0 || depth <= self.max_depth
+    }
+
+    // Get the page budget for a domain, preferring any per-domain override. Zero means there is no limit.
+    pub fn budget_for(&self, domain: &str) -> usize {
+        *self.domain_page_budgets.get(domain).unwrap_or(&self.max_pages_per_domain)
+    }
+
+    // Claim a page from the domain's budget. Returns false if the budget is already spent.
+    pub fn try_claim(&self, url: &Url) -> bool {
+        let domain = match url.host_str() {
+            Some(domain) => domain,
+            None => return false,
+        };
+        let budget = self.budget_for(domain);
+        let mut domain_pages = self.domain_pages.lock().unwrap();
+        let pages = domain_pages.entry(domain.to_string()).or_insert(0);
+        if budget > 0 && *pages >= budget {
+            return false;
+        }
+        *pages += 1;
+        true
+    }
+
+    // Count pages that were claimed by a previous run.
+    pub fn add_claimed(&self, domain: &str, pages: usize) {
+        *self.domain_pages.lock().unwrap().entry(domain.to_string()).or_insert(0) += pages;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(url: &str, depth: usize) -> data::FrontierEntry {
+        data::FrontierEntry::new(Url::parse(url).unwrap(), "STARTING_URL".to_string(), depth, None)
+    }
+
+    fn queue(strategy: &str) -> CrawlQueue {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.crawl_strategy = strategy.to_string();
+        config.priority_domains = vec!["www.priority.com".to_string()];
+        config.priority_patterns = vec!["/news/".to_string()];
+        CrawlQueue::new(&config)
+    }
+
+    #[test]
+    fn test_bfs_pops_in_queued_order() {
+        let mut queue = queue("bfs");
+        queue.push(entry("https://www.example.com/a", 1));
+        queue.push(entry("https://www.example.com/b", 2));
+        assert_eq!(queue.pop().unwrap().url.path(), "/a");
+        assert_eq!(queue.pop().unwrap().url.path(), "/b");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_dfs_pops_newest_first() {
+        let mut queue = queue("dfs");
+        queue.push(entry("https://www.example.com/a", 1));
+        queue.push(entry("https://www.example.com/b", 2));
+        assert_eq!(queue.pop().unwrap().url.path(), "/b");
+        assert_eq!(queue.pop().unwrap().url.path(), "/a");
+    }
+
+    #[test]
+    fn test_priority_prefers_matching_and_shallow_urls() {
+        let mut queue = queue("priority");
+        queue.push(entry("https://www.example.com/deep", 3));
+        queue.push(entry("https://www.example.com/shallow", 1));
+        queue.push(entry("https://www.example.com/news/story", 3));
+        queue.push(entry("https://www.priority.com/page", 4));
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.pop().unwrap().url.path(), "/page");
+        assert_eq!(queue.pop().unwrap().url.path(), "/news/story");
+        assert_eq!(queue.pop().unwrap().url.path(), "/shallow");
+        assert_eq!(queue.pop().unwrap().url.path(), "/deep");
+    }
+
+    #[test]
+    fn test_priority_uses_sitemap_hints() {
+        let mut queue = queue("priority");
+        let fresh = Utc::now().format("%Y-%m-%d").to_string();
+        queue.push(entry("https://www.example.com/plain", 1));
+        queue.push(entry("https://www.example.com/stale", 1).with_sitemap(Some("2001-01-01".to_string()), Some(0.5)));
+        queue.push(entry("https://www.example.com/fresh", 1).with_sitemap(Some(fresh), Some(0.5)));
+        queue.push(entry("https://www.example.com/low", 1).with_sitemap(None, Some(0.0)));
+        assert_eq!(queue.pop().unwrap().url.path(), "/fresh");
+        assert_eq!(queue.pop().unwrap().url.path(), "/stale");
+        assert_eq!(queue.pop().unwrap().url.path(), "/plain");
+        assert_eq!(queue.pop().unwrap().url.path(), "/low");
+    }
+
+    #[test]
+    fn test_domain_budget_with_override() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.max_pages_per_domain = 2;
+        config.domain_page_budgets = HashMap::from([("www.big.com".to_string(), 3)]);
+        let limits = CrawlLimits::new(&config);
+        let small = Url::parse("https://www.small.com/").unwrap();
+        let big = Url::parse("https://www.big.com/").unwrap();
+        limits.add_claimed("www.small.com", 1);
+        assert!(limits.try_claim(&small));
+        assert!(!limits.try_claim(&small));
+        assert!(limits.try_claim(&big));
+        assert!(limits.try_claim(&big));
+        assert!(limits.try_claim(&big));
+        assert!(!limits.try_claim(&big));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.max_depth = 3;
+        let limits = CrawlLimits::new(&config);
+        assert!(limits.within_depth(3));
+        assert!(!limits.within_depth(4));
+        config.max_depth = 0;
+        assert!(CrawlLimits::new(&config).within_depth(100));
+    }
+
+    #[test]
+    fn test_unknown_strategy_falls_back_to_bfs() {
+        assert_eq!(CrawlStrategy::parse("Priority"), Some(CrawlStrategy::Priority));
+        assert_eq!(CrawlStrategy::parse("random"), None);
+        assert_eq!(queue("random").strategy, CrawlStrategy::Bfs);
+    }
+}
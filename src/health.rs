@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::http::FetchError;
+
+// The circuit breaker state for a single host.
+#[derive(Default)]
+struct HostHealth {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    half_open: bool,
+    trips: u32,
+}
+
+// Tracks failing or throttling hosts, and pauses them for a cool-down once they trip.
+pub(crate) struct DomainHealth {
+    failure_threshold: u32,
+    cooldown: Duration,
+    hosts: Mutex<HashMap<String, HostHealth>>,
+}
+
+impl DomainHealth {
+    pub fn new(config: &config::Config) -> Self {
+        DomainHealth {
+            failure_threshold: config.circuit_breaker_threshold,
+            cooldown: Duration::from_secs(config.circuit_breaker_cooldown_secs),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Check if a fetch result counts against the host. Timeouts, connection errors, 5xx and 429 do.
+    pub fn is_failure(error: &FetchError) -> bool {
+        match error {
+            FetchError::Request(_) => true,
+            FetchError::Status(info) => matches!(info.status_code, Some(429) | Some(500..=599)),
+            FetchError::TooLarge(..) | FetchError::NotHtml(_) => false,
+        }
+    }
+
+    // Get how much longer a host is paused for, or None if it can be requested.
+    // Once the cool-down passes the host is half-open, and a single failure trips it again.
+    pub fn cooldown_remaining(&self, host: &str) -> Option<Duration> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let health = hosts.get_mut(host)?;
+        let open_until = health.open_until?;
+        let now = Instant::now();
+        if now < open_until {
+            return Some(open_until - now);
+        }
+        health.open_until = None;
+        health.half_open = true;
+        None
+    }
+
+    pub fn record_success(&self, host: &str) {
+        if let Some(health) = self.hosts.lock().unwrap().get_mut(host) {
+            health.consecutive_failures = 0;
+            health.half_open = false;
+        }
+    }
+
+    // Count a failure against the host. Returns true if this trips the circuit breaker.
+    pub fn record_failure(&self, host: &str) -> bool {
+        if self.failure_threshold == 0 {
+            return false;
+        }
+        let mut hosts = self.hosts.lock().unwrap();
+        let health = hosts.entry(host.to_string()).or_default();
+        health.consecutive_failures += 1;
+        if health.open_until.is_some() || (!health.half_open && health.consecutive_failures < self.failure_threshold) {
+            return false;
+        }
+        health.open_until = Some(Instant::now() + self.cooldown);
+        health.half_open = false;
+        health.consecutive_failures = 0;
+        health.trips += 1;
+        true
+    }
+
+    // Get every host that tripped during the crawl, and how many times.
+    pub fn tripped_domains(&self) -> Vec<(String, u32)> {
+        let hosts = self.hosts.lock().unwrap();
+        let mut tripped: Vec<(String, u32)> = hosts.iter()
+            .filter(|(_, health)| health.trips > 0)
+            .map(|(host, health)| (host.clone(), health.trips))
+            .collect();
+        tripped.sort();
+        tripped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(threshold: u32, cooldown_secs: u64) -> DomainHealth {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.circuit_breaker_threshold = threshold;
+        config.circuit_breaker_cooldown_secs = cooldown_secs;
+        DomainHealth::new(&config)
+    }
+
+    #[test]
+    fn test_trips_after_consecutive_failures() {
+        let health = health(3, 60);
+        assert!(!health.record_failure("www.example.com"));
+        assert!(!health.record_failure("www.example.com"));
+        health.record_success("www.example.com");
+        assert!(!health.record_failure("www.example.com"));
+        assert!(!health.record_failure("www.example.com"));
+        assert!(health.cooldown_remaining("www.example.com").is_none());
+        assert!(health.record_failure("www.example.com"));
+        assert!(health.cooldown_remaining("www.example.com").is_some());
+        assert!(health.cooldown_remaining("www.other.com").is_none());
+        assert_eq!(health.tripped_domains(), vec![("www.example.com".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_half_open_trips_on_one_failure() {
+        let health = health(2, 0);
+        health.record_failure("www.example.com");
+        assert!(health.record_failure("www.example.com"));
+        // The cool-down has passed, so the host is half-open.
+        assert!(health.cooldown_remaining("www.example.com").is_none());
+        assert!(health.record_failure("www.example.com"));
+        assert_eq!(health.tripped_domains(), vec![("www.example.com".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_disabled_with_zero_threshold() {
+        let health = health(0, 60);
+        for _ in 0..10 {
+            assert!(!health.record_failure("www.example.com"));
+        }
+        assert!(health.tripped_domains().is_empty());
+    }
+}
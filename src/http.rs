@@ -1,292 +1,456 @@
-use reqwest::{Error, Url, header::{self, HeaderValue}};
+use reqwest::{Client, Error, Response, Url, header::{self, HeaderValue}, redirect};
 use rusqlite::Connection;
-use std::{path::Path, sync::{Arc, Mutex}};
+use std::{fmt, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use lazy_static::lazy_static;
 use rand::seq::SliceRandom;
+use regex::Regex;
 
-use crate::{config, sqlite, tools};
+use crate::{canonical, config, data, retry, sqlite, tools};
 
-// Fetch HTML from a given URL
-pub(crate) fn fetch_html(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, url: Url) -> Result<String, Error> {
-    // Create a new HTTP client
-    let client = reqwest::blocking::Client::builder()
-    .timeout(std::time::Duration::from_secs(config.crawler_request_timeout))
-    .build()
-    .unwrap();
+lazy_static! {
+    // A link in a Link header, with its target and parameters.
+    static ref LINK_HEADER_ENTRY: Regex = Regex::new(r"<([^>]*)>((?:\s*;[^,;]*)*)").unwrap();
+}
+
+// Build the HTTP client shared by every request, so connections and TLS sessions are pooled and reused.
+pub(crate) fn build_client(config: &config::Config) -> Result<Client, Box<dyn std::error::Error>> {
+    let mut default_headers = header::HeaderMap::new();
+    for (name, value) in config.default_headers.iter() {
+        default_headers.insert(header::HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+    }
+    let redirect_policy = if config.max_redirects == 0 {
+        redirect::Policy::none()
+    } else {
+        redirect::Policy::limited(config.max_redirects)
+    };
 
+    let client = Client::builder()
+        .timeout(Duration::from_secs(config.crawler_request_timeout))
+        .connect_timeout(Duration::from_secs(config.crawler_connect_timeout))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout))
+        .default_headers(default_headers)
+        .gzip(config.compression)
+        .brotli(config.compression)
+        .deflate(config.compression)
+        .redirect(redirect_policy)
+        .build()?;
+    Ok(client)
+}
+
+// Pick the user agent for a request
+fn pick_user_agent(config: &config::Config) -> &String {
     // Randomly pick a user agent from the list
     let mut user_agent = config.user_agents.first().unwrap();
     if config.rotate_user_agents {
         user_agent = config.user_agents.choose(&mut rand::thread_rng()).unwrap();
     }
+    user_agent
+}
 
-    // Send a GET request to the specified URL and get a response
+// Why a fetch didn't produce a usable body.
+#[derive(Debug)]
+pub(crate) enum FetchError {
+    // The request failed before we got a complete response, such as a timeout or connection error.
+    Request(Error),
+    // The server responded, but not with a 2xx status.
+    Status(Box<data::ResponseInfo>),
+    // The body went over the byte limit, so we stopped reading it.
+    TooLarge(Box<data::ResponseInfo>, u64),
+    // The page isn't HTML, so its body wasn't read.
+    NotHtml(Box<data::ResponseInfo>),
+}
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Request(e) => write!(f, "{}", e),
+            FetchError::Status(info) => write!(f, "HTTP status {}", info.status_code.unwrap_or(0)),
+            FetchError::TooLarge(_, max_bytes) => write!(f, "Body is over the {} byte limit", max_bytes),
+            FetchError::NotHtml(info) => write!(f, "Not an HTML page: {}", info.content_type.as_deref().unwrap_or("")),
+        }
+    }
+}
+impl std::error::Error for FetchError {}
+
+// Send a GET request, and record what we learn about the response.
+// Returns the response with its headers read, so the caller can decide how to read the body.
+async fn send_request(config: &config::Config, client: &Client, url: &Url, info: &mut data::ResponseInfo, start: Instant) -> Result<Response, Error> {
     let res = client.get(url.clone())
-        .header(header::USER_AGENT, HeaderValue::from_str(user_agent).unwrap())
+        .header(header::USER_AGENT, HeaderValue::from_str(pick_user_agent(config)).unwrap())
         .send()
-        .map_err(|err| {
-            err
-        })?;
-    
-    // Get the body of the response as a String
-    let body = res.text().map_err(|err| {
-        err
-    })?;
-    
-    // Fetch any images from the page
-    if config.collect_html {
-        match sqlite::insert_html(&db_conn.lock().unwrap(), &tools::format_url_for_storage(url.to_string()), &body.trim().to_string()) {
-            Ok(_) => (),
-            Err(e) => eprintln!("Failed to insert HTML into SQLite: {}", e),
+        .await?;
+    info.ttfb = start.elapsed();
+    info.final_url = Some(res.url().clone());
+    info.status_code = Some(res.status().as_u16());
+    info.content_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+    info.content_length = res.content_length();
+    info.retry_after = res.headers().get(header::RETRY_AFTER).and_then(|v| v.to_str().ok()).and_then(retry::parse_retry_after);
+    info.canonical_link = res.headers().get_all(header::LINK).iter()
+        .filter_map(|v| v.to_str().ok())
+        .find_map(parse_link_canonical);
+    info.robots_tags = res.headers().get_all("x-robots-tag").iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .collect();
+    info.headers = config.recorded_headers.iter().filter_map(|name| {
+        res.headers().get(name.as_str()).and_then(|v| v.to_str().ok()).map(|v| (name.to_lowercase(), v.to_string()))
+    }).collect();
+    Ok(res)
+}
+
+// Store the response details in the responses table
+fn record_response(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, info: &data::ResponseInfo) {
+    if let Err(e) = sqlite::insert_response(&db_conn.lock().unwrap(), &canonical::canonicalize(config, info.url.as_str()), info) {
+        tools::debug_log(config.debug, &format!("Failed to insert response for {} into SQLite: {}", info.url, e));
+    }
+}
+
+// Fetch HTML from a given URL
+pub(crate) async fn fetch_html(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: Url, attempt: u32) -> Result<(String, data::ResponseInfo), FetchError> {
+    // Send a GET request to the specified URL and get a response
+    let start = Instant::now();
+    let mut info = data::ResponseInfo::new(url.clone());
+    info.attempt = attempt;
+    let body = match send_request(config, client, &url, &mut info, start).await {
+        // Only HTML is parsed for links and text. Anything else, like a PDF or a JSON feed, is recorded and skipped without reading its body.
+        Ok(_) if info.is_success() && info.content_type.as_deref().is_some_and(|content_type| !is_html_content_type(content_type)) => {
+            info.duration = start.elapsed();
+            let error = FetchError::NotHtml(Box::new(info.clone()));
+            info.error = Some(error.to_string());
+            record_response(config, db_conn, &info);
+            return Err(error);
+        }
+        // Get the body of the response as a String
+        Ok(res) => res.text().await,
+        Err(e) => Err(e),
+    };
+    info.duration = start.elapsed();
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            info.error = Some(e.to_string());
+            record_response(config, db_conn, &info);
+            return Err(FetchError::Request(e));
         }
+    };
+    if info.content_length.is_none() {
+        info.content_length = Some(body.len() as u64);
+    }
+    record_response(config, db_conn, &info);
+
+    // Don't treat error pages as a successful crawl
+    if !info.is_success() {
+        return Err(FetchError::Status(Box::new(info)));
     }
 
     // Return the body of the response
-   Ok(body)
+   Ok((body, info))
 }
 
-// Fetch image binary data from a given URL
-pub(crate) fn fetch_image(config: &config::Config, url: &Url) -> Result<Vec<u8>, reqwest::Error> {
-    // Create a new HTTP client
-    let client = reqwest::blocking::Client::builder()
-    .timeout(std::time::Duration::from_secs(config.crawler_request_timeout))
-    .build()
-    .unwrap();
+// Check if a Content-Type header is for an HTML or XHTML page, ignoring any charset.
+pub(crate) fn is_html_content_type(content_type: &str) -> bool {
+    let mime_type = content_type.split(';').next().unwrap_or("").trim();
+    mime_type.eq_ignore_ascii_case("text/html") || mime_type.eq_ignore_ascii_case("application/xhtml+xml")
+}
 
-    // Randomly pick a user agent from the list
-    let mut user_agent =config.user_agents.first().unwrap();
-    if config.rotate_user_agents {
-        user_agent = config.user_agents.choose(&mut rand::thread_rng()).unwrap();
+// Store the HTML of a page
+pub(crate) fn save_html(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, url: &Url, html: &str) {
+    match sqlite::insert_html(&db_conn.lock().unwrap(), &canonical::canonicalize(config, url.as_str()), &html.trim().to_string()) {
+        Ok(_) => (),
+        Err(e) => eprintln!("Failed to insert HTML into SQLite: {}", e),
     }
+}
+
+// Find the rel="canonical" target in a Link header, such as `<https://www.example.com/a>; rel="canonical"`.
+pub(crate) fn parse_link_canonical(value: &str) -> Option<String> {
+    let canonical = LINK_HEADER_ENTRY.captures_iter(value).find_map(|link| {
+        let is_canonical = link[2].split(';').any(|param| {
+            let (name, rel) = param.split_once('=').unwrap_or((param, ""));
+            name.trim().eq_ignore_ascii_case("rel")
+                && rel.trim().trim_matches('"').split_whitespace().any(|rel| rel.eq_ignore_ascii_case("canonical"))
+        });
+        is_canonical.then(|| link[1].trim().to_string())
+    });
+    canonical
+}
 
+// Fetch image binary data from a given URL
+pub(crate) async fn fetch_image(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> Result<Vec<u8>, FetchError> {
     // Send a GET request to the specified URL and get a response
-    let res = client.get(url.clone())
-        .header(header::USER_AGENT, HeaderValue::from_str(user_agent).unwrap())
-        .send()
-        .map_err(|err| {
-            err
-        })?;
+    let start = Instant::now();
+    let mut info = data::ResponseInfo::new(url.clone());
+    let res = match send_request(config, client, url, &mut info, start).await {
+        Ok(res) => res,
+        Err(e) => {
+            info.duration = start.elapsed();
+            info.error = Some(e.to_string());
+            record_response(config, db_conn, &info);
+            return Err(FetchError::Request(e));
+        }
+    };
+    if !info.is_success() {
+        info.duration = start.elapsed();
+        record_response(config, db_conn, &info);
+        return Err(FetchError::Status(Box::new(info)));
+    }
 
     // Check if the Content-Type is an image
-    let content_type = res.headers().get(header::CONTENT_TYPE);
-    if let Some(content_type) = content_type {
-        if !content_type.to_str().unwrap().starts_with("image/") {
+    if let Some(content_type) = &info.content_type {
+        if !content_type.starts_with("image/") {
+            info.duration = start.elapsed();
+            record_response(config, db_conn, &info);
             tools::debug_log(config.debug, &format!("The body of the response is not an image: {}", url));
             return Ok(Vec::new());
         }
     }
 
     // Get the body of the response as bytes
-    let bytes = res.bytes().map_err(|err| {
-        eprintln!("Failed to read image data: {}", err);
-        err
-    })?;
+    let bytes = res.bytes().await;
+    info.duration = start.elapsed();
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to read image data: {}", e);
+            info.error = Some(e.to_string());
+            record_response(config, db_conn, &info);
+            return Err(FetchError::Request(e));
+        }
+    };
+    if info.content_length.is_none() {
+        info.content_length = Some(bytes.len() as u64);
+    }
+    record_response(config, db_conn, &info);
 
     Ok(bytes.to_vec())
 }
 
-// Handle any relative paths that we've encountered.
-pub(crate) fn handle_relative_paths(config: &config::Config, url: &str, referrer_url: &String) -> Result<String, (Option<Url>, bool)> {
-    let mut formatted_url = url.trim().to_string();
-    // Remove any anchors from the URL
-    if let Some(index) = url.find("#") {
-        formatted_url = formatted_url[..index].trim().to_string();
-    } 
-
-    if formatted_url.starts_with("www") || formatted_url.starts_with("http") {
-        // This is a valid URL
-        return Ok(formatted_url);
-    } else if formatted_url == "" || formatted_url == "/" || formatted_url == "#" || formatted_url.starts_with("?") || formatted_url == "\\\"" || formatted_url == "..//"{
-        // Skip any empty URLs
-        return Err((None, false));
-    }
-
-    // Handle any relative paths
-    if formatted_url.starts_with("mailto") || formatted_url.starts_with("whatsapp") || formatted_url.starts_with("fb-messenger") || 
-        formatted_url.starts_with("tel") || formatted_url.starts_with("sms") || formatted_url.starts_with("facetime") || 
-        formatted_url.starts_with("skype") || formatted_url.starts_with("slack") || formatted_url.starts_with("zoom") {
-        return Err((None, false));
-    } else if formatted_url.starts_with("itms") || formatted_url.starts_with("market") { 
-        // Apple App Store or Google Play Store
-        return Err((None, false));
-    } else if formatted_url.starts_with("javascript") || formatted_url.starts_with("vbscript") || formatted_url.starts_with("javscript") {
-        return Err((None, false));
-    } else if formatted_url.contains(":invalid") {
-        return Err((None, false));
-    } else if formatted_url.starts_with("data:image") {
-        // Data URL, such as a base64 image path. Maybe these are worth your time.
-        return Err((None, false));
-    } else if formatted_url.starts_with("clkn/http/") {
-        // This is a redirect URL from Google Ads.
-        formatted_url = format!("http://{}", formatted_url.trim_start_matches("clkn/http/"));
-    } else if formatted_url.starts_with("clkn/rel/") {
-        // This is a redirect URL from Google Ads.
-        // Relative path to a url. such as "/politics/congress".
-        let ref_url = Url::parse(referrer_url);
-        if ref_url.is_err() {
-            tools::debug_log(config.debug, &format!("Invalid referrer URL: {}", referrer_url));
-            return Err((None, false));
-        }
-        let ref_url = ref_url.unwrap();
-        let ref_domain = ref_url.domain().unwrap_or("").to_string();
-        formatted_url = format!("{}{}", ref_domain, formatted_url.trim_start_matches("clkn/rel/"));
-    } else if formatted_url.starts_with("//") {
-        // Protocol-relative URL. such as "//www.cnn.com".
-        formatted_url = format!("https:{}", formatted_url);
-    } else if formatted_url.starts_with("/") {
-        // Relative path to a url. such as "/politics/congress".
-        let ref_url = Url::parse(referrer_url);
-        if ref_url.is_err() {
-            tools::debug_log(config.debug, &format!("Invalid referrer URL: {}", referrer_url));
-            return Err((None, false));
+// Fetch the raw bytes of a sitemap or feed, which may be gzip-compressed.
+// The body is read in chunks and dropped once it goes over max_bytes, rather than held in memory.
+pub(crate) async fn fetch_xml(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url, max_bytes: u64) -> Result<Vec<u8>, FetchError> {
+    let start = Instant::now();
+    let mut info = data::ResponseInfo::new(url.clone());
+    let mut res = match send_request(config, client, url, &mut info, start).await {
+        Ok(res) => res,
+        Err(e) => {
+            info.duration = start.elapsed();
+            info.error = Some(e.to_string());
+            record_response(config, db_conn, &info);
+            return Err(FetchError::Request(e));
         }
-        let ref_url = ref_url.unwrap();
-        let ref_domain = ref_url.domain().unwrap_or("").to_string();
-        formatted_url = format!("{}{}", ref_domain, formatted_url);
-    } else if formatted_url.starts_with("../") || formatted_url.starts_with("./../"){
-        // is ./../ the same as ../
-        if formatted_url.starts_with("./") {
-            formatted_url = formatted_url[2..].to_string();
-        }
-        // Relative path to a url. such as "../politics/congress".
-        let ref_url = Url::parse(referrer_url).unwrap();
-        let mut path = Path::new(ref_url.path());
-        while formatted_url.starts_with("../") {
-            formatted_url = formatted_url[3..].to_string();
-            if let Some(parent) = path.parent() {
-                path = parent;
+    };
+    let mut body = Vec::new();
+    loop {
+        match res.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk);
+                if body.len() as u64 > max_bytes {
+                    info.duration = start.elapsed();
+                    let error = FetchError::TooLarge(Box::new(info.clone()), max_bytes);
+                    info.error = Some(error.to_string());
+                    record_response(config, db_conn, &info);
+                    return Err(error);
+                }
             }
-        }
-        let mut mutable_ref_url = ref_url.clone();
-        mutable_ref_url.set_path(path.to_str().unwrap());
-        // Handle the slash at the end of the path
-        if !mutable_ref_url.as_str().ends_with("/") && !formatted_url.starts_with("/") {
-            mutable_ref_url.set_path(format!("{}/", mutable_ref_url.path()).as_str());
-        }
-        formatted_url = format!("{}{}", mutable_ref_url, formatted_url.trim_start_matches(".."));
-    } else if formatted_url.starts_with("./") {
-        // Another folder up, such as "./politics/congress".
-        let ref_url = Url::parse(referrer_url).unwrap();
-        let mut mutable_ref_url = ref_url.clone();
-        if !mutable_ref_url.as_str().ends_with("/") && !formatted_url.starts_with("/") {
-            mutable_ref_url.set_path(format!("{}/", mutable_ref_url.path()).as_str());
-        }
-        formatted_url = format!("{}{}", mutable_ref_url, formatted_url.trim_start_matches("./"));
-    } else {
-        // Likely a relative path to a url. such as "politics/congress.html".
-        let ref_url = Url::parse(referrer_url).unwrap();
-        let mut mutable_ref_url = ref_url.clone();
-        // If we're looking at a file path, cut back to the folder
-        let mut path = Path::new(ref_url.path());
-        if mutable_ref_url.as_str().ends_with(".html") {
-            if let Some(parent) = path.parent() {
-                path = parent;
+            Ok(None) => break,
+            Err(e) => {
+                info.duration = start.elapsed();
+                info.error = Some(e.to_string());
+                record_response(config, db_conn, &info);
+                return Err(FetchError::Request(e));
             }
-            mutable_ref_url.set_path(path.to_str().unwrap());
         }
-        if !mutable_ref_url.as_str().ends_with("/") && !formatted_url.starts_with("/") {
-            mutable_ref_url.set_path(format!("{}/", mutable_ref_url.path()).as_str());
-        }
-        formatted_url = format!("{}{}", mutable_ref_url, formatted_url);
     }
+    info.duration = start.elapsed();
+    if info.content_length.is_none() {
+        info.content_length = Some(body.len() as u64);
+    }
+    record_response(config, db_conn, &info);
+    if !info.is_success() {
+        return Err(FetchError::Status(Box::new(info)));
+    }
+    Ok(body)
+}
 
-    if config.log_relative_paths {
-        if formatted_url != url {
-            tools::debug_log(config.debug, &format!("Formatted Relative URL [{}] to [{}] from [{}]", url, formatted_url, referrer_url));
+// Fetch a robots.txt, whatever its status code. Reading stops once the body passes ROBOTS_MAX_BYTES.
+pub(crate) async fn fetch_robots_txt(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> Result<(u16, String), FetchError> {
+    let start = Instant::now();
+    let mut info = data::ResponseInfo::new(url.clone());
+    let mut res = match send_request(config, client, url, &mut info, start).await {
+        Ok(res) => res,
+        Err(e) => {
+            info.duration = start.elapsed();
+            info.error = Some(e.to_string());
+            record_response(config, db_conn, &info);
+            return Err(FetchError::Request(e));
+        }
+    };
+    let mut body = Vec::new();
+    loop {
+        match res.chunk().await {
+            Ok(Some(chunk)) => {
+                body.extend_from_slice(&chunk);
+                if config.robots_max_bytes > 0 && body.len() > config.robots_max_bytes {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                info.duration = start.elapsed();
+                info.error = Some(e.to_string());
+                record_response(config, db_conn, &info);
+                return Err(FetchError::Request(e));
+            }
         }
     }
-    Ok(formatted_url)
+    info.duration = start.elapsed();
+    if info.content_length.is_none() {
+        info.content_length = Some(body.len() as u64);
+    }
+    record_response(config, db_conn, &info);
+    Ok((info.status_code.unwrap_or(0), String::from_utf8_lossy(&body).to_string()))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn test_handle_relative_paths_valid_url() {
-        let url = "http://www.example.com";
-        let referrer_url = &"http://www.referrer.com".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), url);
+// Resolve a link found on a page into an absolute URL, following RFC 3986 and the WHATWG URL rules.
+// The base is the page's final URL after redirects. Links that don't lead to another crawlable page return None.
+pub(crate) fn resolve_url(config: &config::Config, link: &str, base: &Url) -> Option<Url> {
+    let link = link.trim();
+    // Empty and fragment-only links point back at the same page.
+    if link.is_empty() || link.starts_with('#') {
+        return None;
     }
 
-    #[test]
-    fn test_handle_relative_paths_anchor() {
-        let url = "http://www.example.com#anchor";
-        let referrer_url = &"http://www.referrer.com".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com");
-    }
+    // Unwrap redirect URLs from Google Ads, such as "clkn/http/www.cnn.com" or "clkn/rel/politics".
+    let link = if let Some(rest) = link.strip_prefix("clkn/http/") {
+        format!("http://{}", rest)
+    } else if let Some(rest) = link.strip_prefix("clkn/rel/") {
+        format!("/{}", rest.trim_start_matches('/'))
+    } else {
+        link.to_string()
+    };
 
-    #[test]
-    fn test_handle_relative_paths_relative_path() {
-        let url = "/relative/path";
-        let referrer_url = &"http://www.example.com".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "www.example.com/relative/path");
+    let mut url = match base.join(&link) {
+        Ok(url) => url,
+        Err(e) => {
+            tools::debug_log(config.debug, &format!("Failed to resolve [{}] against [{}]: {}", link, base, e));
+            return None;
+        }
+    };
+    // Skip mailto, javascript, data, app store links and the like.
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
     }
+    url.set_fragment(None);
 
-    #[test]
-    fn test_handle_relative_paths_protocol_relative_url() {
-        let url = "//www.example.com";
-        let referrer_url = &"http://www.referrer.com".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "https://www.example.com");
+    if config.log_relative_paths && url.as_str() != link {
+        tools::debug_log(config.debug, &format!("Resolved relative URL [{}] to [{}] from [{}]", link, url, base));
     }
+    Some(url)
+}
 
-    #[test]
-    fn test_handle_relative_paths_relative_path_with_dot_dot() {
-        let url = "../relative/path";
-        let referrer_url = &"http://www.example.com/folder".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com/relative/path");
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[test]
-    fn test_handle_relative_paths_relative_path_with_double_dot_dot() {
-        let url = "../../relative/path";
-        let referrer_url = &"http://www.example.com/folder/folder2".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com/relative/path");
+    fn resolve(link: &str, base: &str) -> Option<String> {
+        let config = config::Config::new("crab.json".to_string());
+        resolve_url(&config, link, &Url::parse(base).unwrap()).map(|url| url.to_string())
     }
 
     #[test]
-    fn test_handle_relative_paths_relative_path_with_dot() {
-        let url = "./relative/path";
-        let referrer_url = &"http://www.example.com/folder".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com/folder/relative/path");
+    fn test_is_html_content_type() {
+        assert!(is_html_content_type("text/html"));
+        assert!(is_html_content_type("Text/HTML; charset=utf-8"));
+        assert!(is_html_content_type("application/xhtml+xml"));
+        assert!(!is_html_content_type("application/pdf"));
+        assert!(!is_html_content_type("application/json; charset=utf-8"));
+        assert!(!is_html_content_type("text/plain"));
     }
 
     #[test]
-    fn test_handle_relative_paths_relative_path_without_slash() {
-        let url = "relative/path";
-        let referrer_url = &"http://www.example.com/folder".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com/folder/relative/path");
+    fn test_parse_link_canonical() {
+        assert_eq!(parse_link_canonical(r#"<https://www.example.com/a>; rel="canonical""#).as_deref(), Some("https://www.example.com/a"));
+        assert_eq!(parse_link_canonical(r#"</style.css>; rel=preload; as=style, </a?b=1,2>; rel="Canonical""#).as_deref(), Some("/a?b=1,2"));
+        assert_eq!(parse_link_canonical(r#"<https://www.example.com/b>; rel="alternate canonical"; hreflang=en"#).as_deref(), Some("https://www.example.com/b"));
+        assert_eq!(parse_link_canonical(r#"<https://www.example.com/amp>; rel="amphtml""#), None);
     }
 
     #[test]
-    fn test_handle_relative_paths_relative_file_path_without_slash() {
-        let url = "relative/path";
-        let referrer_url = &"http://www.example.com/file.html".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert_eq!(result.unwrap(), "http://www.example.com/relative/path");
+    fn test_resolve_url_rfc3986_examples() {
+        // RFC 3986 section 5.4, with fragments removed since they don't change the page.
+        let base = "http://a/b/c/d;p?q";
+        let cases = [
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g/"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("g#s", "http://a/b/c/g"),
+            ("g?y#s", "http://a/b/c/g?y"),
+            (";x", "http://a/b/c/;x"),
+            ("g;x", "http://a/b/c/g;x"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("../../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            (".g", "http://a/b/c/.g"),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("http:g", "http://a/b/c/g"),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(resolve(link, base).as_deref(), Some(expected), "resolving {}", link);
+        }
     }
 
     #[test]
-    fn test_handle_relative_paths_invalid_url() {
-        let url = "url:invalid";
-        let referrer_url = &"http://www.referrer.com".to_string();
-        let config: config::Config = config::Config::new("crab.json".to_string());
-        let result = handle_relative_paths(&config, url, referrer_url);
-        assert!(result.is_err());
+    fn test_resolve_url_whatwg_edge_cases() {
+        let cases = [
+            ("http://www.example.com", "http://www.referrer.com/", Some("http://www.example.com/")),
+            ("http://www.example.com#anchor", "http://www.referrer.com/", Some("http://www.example.com/")),
+            ("/relative/path", "http://www.example.com", Some("http://www.example.com/relative/path")),
+            ("//www.example.com", "https://www.referrer.com/", Some("https://www.example.com/")),
+            ("//www.example.com", "http://www.referrer.com/", Some("http://www.example.com/")),
+            ("../relative/path", "http://www.example.com/folder", Some("http://www.example.com/relative/path")),
+            ("../../relative/path", "http://www.example.com/a/b/c", Some("http://www.example.com/relative/path")),
+            ("./relative/path", "http://www.example.com/folder/", Some("http://www.example.com/folder/relative/path")),
+            ("relative/path", "http://www.example.com/folder", Some("http://www.example.com/relative/path")),
+            ("relative/path", "http://www.example.com/file.html", Some("http://www.example.com/relative/path")),
+            ("?page=2", "http://www.example.com/list?page=1", Some("http://www.example.com/list?page=2")),
+            ("  /padded\n", "http://www.example.com/", Some("http://www.example.com/padded")),
+            ("/tab\tbed", "http://www.example.com/", Some("http://www.example.com/tabbed")),
+            ("\\backslash\\path", "http://www.example.com/a/", Some("http://www.example.com/backslash/path")),
+            ("/a b", "http://www.example.com/", Some("http://www.example.com/a%20b")),
+            ("HTTP://WWW.EXAMPLE.COM:80/Path", "http://www.referrer.com/", Some("http://www.example.com/Path")),
+            ("http://b\u{fc}cher.example/", "http://www.referrer.com/", Some("http://xn--bcher-kva.example/")),
+            ("clkn/http/www.example.com/ad", "http://www.referrer.com/", Some("http://www.example.com/ad")),
+            ("clkn/rel/politics", "http://www.example.com/news/story", Some("http://www.example.com/politics")),
+            ("", "http://www.example.com/", None),
+            ("#top", "http://www.example.com/", None),
+            ("mailto:someone@example.com", "http://www.example.com/", None),
+            ("javascript:void(0)", "http://www.example.com/", None),
+            ("data:image/png;base64,AAAA", "http://www.example.com/", None),
+            ("tel:+15555555555", "http://www.example.com/", None),
+            ("itms-apps://itunes.apple.com/app", "http://www.example.com/", None),
+            ("url:invalid", "http://www.example.com/", None),
+            ("http://[::1", "http://www.example.com/", None),
+        ];
+        for (link, base, expected) in cases {
+            assert_eq!(resolve(link, base).as_deref(), expected, "resolving {} against {}", link, base);
+        }
     }
-}
\ No newline at end of file
+}
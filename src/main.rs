@@ -1,12 +1,18 @@
 use std::sync::Arc;
-use rayon::{ThreadPool, ThreadPoolBuilder};
-use reqwest::Url;
-use std::{
-    thread,
-    sync::mpsc::{self, RecvTimeoutError},
-    time::Duration,
-};
+use std::time::Duration;
 mod crawl;
+mod frontier;
+mod politeness;
+mod retry;
+mod health;
+mod canonical;
+mod traps;
+mod rules;
+mod domains;
+mod sitemaps;
+mod feeds;
+mod robots;
+mod text;
 mod tools;
 mod sqlite;
 mod http;
@@ -22,14 +28,75 @@ Configuration Options:
 // Site Settings
 - `PERMITTED_DOMAINS`: An array of domain names that the crawler is allowed to visit.
 - `BLACKLIST_DOMAINS`: An array of domain names that the crawler is banned from visiting.
-- `STARTING_URL`: The URL that the crawler starts from.
+  Entries can be exact hosts, `*.example.com` for a domain and its subdomains, `site:example.com` for a registrable domain
+  (`site:seed` for every seed's), or IP addresses and CIDR ranges.
+- `STARTING_URL`: The URL that the crawler starts from, if no seeds are given.
+- `SEEDS`: A list of URLs that the crawler starts from.
+- `SEEDS_FILE`: A file of URLs to start from, as plain text, CSV, TSV or a sitemap, picked by its extension.
+  Blank lines and lines starting with # are skipped. Seeds go through the same filters as links.
+- `SEED_SCOPE`: Keeps the crawl from each seed on its `host` or registrable `domain`, in place of `PERMITTED_DOMAINS`
+  and `FREE_CRAWL`. `none` to turn it off.
+- `LIST_MODE`: A boolean that, if true, only fetches the seeds and doesn't follow any of their links.
+
+// Crawl Strategy Settings
+- `CRAWL_STRATEGY`: The order queued URLs are crawled in, one of `bfs`, `dfs` or `priority`.
+- `PRIORITY_DOMAINS`: Domains the `priority` strategy crawls first.
+- `PRIORITY_PATTERNS`: URL regex patterns the `priority` strategy crawls first.
 
 // Crawler Settings
 - `MAX_URLS_TO_VISIT`: The maximum number of URLs that the crawler will visit before stopping.
-- `MAX_THREADS`: The maximum number of threads that the crawler will use.
+- `MAX_DEPTH`: The maximum number of links the crawler will follow from the starting URL.
+- `MAX_PAGES_PER_DOMAIN`: The maximum number of pages the crawler will take from a single host.
+- `DOMAIN_PAGE_BUDGETS`: Per-host overrides for `MAX_PAGES_PER_DOMAIN`.
+- `TRAP_MAX_REPEATED_SEGMENTS`: The number of times a path segment can repeat before the URL is treated as a spider trap.
+- `TRAP_MAX_PATH_DEPTH`: The deepest path, in segments, the crawler will follow.
+- `TRAP_MAX_URL_LENGTH`: The longest URL the crawler will follow.
+- `TRAP_MAX_QUERY_VARIANTS`: The number of different query strings the crawler will follow for a single path.
+- `TRAP_MAX_URLS_PER_PATTERN`: The number of URLs the crawler will follow per path pattern, with numbers generalised.
+- `TRAP_PATTERN_BUDGETS`: Path regex patterns with their own URL budgets.
+- `URL_RULES`: Ordered allow and deny rules for URLs, each with a glob or regex pattern. The first match decides.
+- `URL_RULES_DEFAULT`: Whether URLs that match no rule are allowed or denied.
+- `DISCOVER_SITEMAPS`: A boolean that, if true, queues the pages in each host's sitemaps, found from robots.txt and /sitemap.xml.
+- `SITEMAP_MAX_URLS`: The number of sitemap pages the crawler will queue per host. 0 for no limit.
+- `DISCOVER_FEEDS`: A boolean that, if true, fetches the RSS and Atom feeds pages link to and queues their items.
+- `FEED_MAX_ITEMS`: The number of items the crawler will queue from each feed. 0 for no limit.
+- `MAX_THREADS`: The maximum number of threads that the crawler will use to parse pages.
+- `MAX_CONCURRENT_REQUESTS`: The maximum number of requests the crawler will have in flight at once.
 - `CRAWLER_TIMEOUT`: The maximum time the crawler will run.
 - `CRAWLER_REQUEST_TIMEOUT`: The maximum time the crawler will wait for a request to return.
-- `CRAWLER_REQUEST_DELAY_MS`: The time each crawler thread will wait between visiting sites.
+- `CRAWLER_CONNECT_TIMEOUT`: The maximum time the crawler will wait to connect to a host.
+- `CRAWLER_REQUEST_DELAY_MS`: The minimum time between requests to the same host.
+- `MAX_CRAWL_DELAY_MS`: The longest robots.txt Crawl-delay the crawler will honour.
+- `AUTO_THROTTLE`: A boolean that, if true, adapts each host's delay to its latency and errors.
+- `AUTO_THROTTLE_MIN_DELAY_MS`: The shortest delay the adaptive throttle will use.
+- `AUTO_THROTTLE_MAX_DELAY_MS`: The longest delay the adaptive throttle will use.
+- `AUTO_THROTTLE_TARGET_CONCURRENCY`: The average number of requests the adaptive throttle aims to have in flight to each host.
+- `RETRY_MAX_ATTEMPTS`: The number of times the crawler will try to fetch a page.
+- `RETRY_BASE_DELAY_MS`: The backoff before the first retry, doubled for each attempt after.
+- `RETRY_MAX_DELAY_MS`: The longest the crawler will wait between attempts. A longer Retry-After gives up on the URL.
+- `RETRY_STATUS_CODES`: The HTTP status codes that are retried.
+- `RETRY_ERROR_KINDS`: The request errors that are retried: timeout, connect, body, or request.
+- `CIRCUIT_BREAKER_THRESHOLD`: The consecutive failures or 429s that pause a host. 0 to never pause.
+- `CIRCUIT_BREAKER_COOLDOWN_SECS`: How long a paused host is left alone before the crawler tries it again.
+
+// HTTP Client Settings
+- `POOL_MAX_IDLE_PER_HOST`: The number of idle connections kept open to each host.
+- `POOL_IDLE_TIMEOUT`: How long an idle connection is kept open.
+- `DEFAULT_HEADERS`: Headers sent with every request.
+- `COMPRESSION`: A boolean that enables gzip, brotli and deflate responses.
+- `MAX_REDIRECTS`: The number of redirects the crawler will follow. 0 to follow none.
+- `RECORDED_HEADERS`: Response headers that are saved with each request.
+- `CANONICAL_SORT_QUERY`: A boolean that sorts query parameters, so their order doesn't make a new URL.
+- `CANONICAL_DROP_PARAMS`: Query parameters removed from URLs, such as tracking and session IDs. A trailing * matches any suffix.
+- `CANONICAL_KEEP_PARAMS`: Query parameters that are never removed, even if they match `CANONICAL_DROP_PARAMS`.
+- `CANONICAL_FOLD_WWW`: A boolean that treats www.example.com and example.com as the same site.
+- `CANONICAL_FOLD_SCHEME`: A boolean that treats the http and https versions of a URL as the same page.
+
+// Data Collection Options
+- `COLLECT_HTML`: A boolean that enables storing the HTML of each page.
+- `COLLECT_IMAGES`: A boolean that enables storing the images on each page.
+- `COLLECT_TEXT`: A boolean that enables storing the main text of each page, without its navigation, footer, scripts or ads.
+- `SKIP_DUPLICATE_CANONICALS`: A boolean that skips storing a page's content if its rel=canonical URL was already captured.
 
 // Logging Options
 - `DEBUG`: A boolean that enables debug output.
@@ -39,13 +106,24 @@ Configuration Options:
 - `SQLITE_ENABLED`: A boolean that enables pushing results to SQLite. 
 - `SQLITE_PATH`: The path to the SQLite database file.
 
+Command Line Options:
+- `-c <path>`: A JSON config file that overrides the defaults.
+- `--resume`: Continue an interrupted crawl from the frontier stored in SQLite.
+- `--seeds <path>`: A seeds file of plain text, CSV or a sitemap, overriding `SEEDS_FILE`. `-` reads the seeds from stdin.
+- `--list`: Turn on `LIST_MODE`.
+
 // Features
 - `FREE_CRAWL`: A boolean that, if true, allows the crawler to visit any domain. This will respect the Blacklist.
 - `ROTATE_USER_AGENTS`: A boolean that enables user agent rotation.
-- `RESPECT_ROBOTS`: A boolean that enables respecting robots.txt files.
+- `RESPECT_ROBOTS`: A boolean that enables respecting robots.txt files, and each page's noindex and nofollow directives.
+- `RESPECT_NOFOLLOW_LINKS`: A boolean that skips links marked rel=nofollow, ugc or sponsored.
+- `ROBOTS_USER_AGENT`: The product token matched against robots.txt `User-agent` groups. It isn't sent as the User-Agent header.
+- `ROBOTS_CACHE_TTL_SECS`: How long a fetched robots.txt is used before it's fetched again, including across runs.
+- `ROBOTS_MAX_BYTES`: The most of a robots.txt the crawler will read. 0 for no limit.
 
 Output:
 - The program outputs the URLs of all visited pages to a sqlite db.
+- The status, timings and selected headers of every request are stored in the responses table.
 - If `DEBUG` is true, the program outputs debug information to the console.
 - If `LIVE_LOGGING` is true, the program outputs the URLs of all visited pages to the console.
 */
@@ -53,9 +131,16 @@ Output:
 fn main() {
     // Check if the user defined a custom config file
     let config_path = tools::get_config_path();
+    let resume = tools::get_resume_flag();
 
-    // Create a new config
-    let config = config::Config::new(config_path);
+    // Create a new config, with any overrides from the command line
+    let mut config = config::Config::new(config_path);
+    if let Some(seeds_path) = tools::get_seeds_path() {
+        config.seeds_file = seeds_path;
+    }
+    if tools::get_list_mode_flag() {
+        config.list_mode = true;
+    }
     let config_clone = config.clone();
     
     // Connect to the SQLite database and run any migrations
@@ -67,39 +152,71 @@ fn main() {
         }
     };
 
+    // Start the async runtime. HTML parsing runs on its blocking pool, capped at MAX_THREADS.
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .max_blocking_threads(config.max_threads.max(1))
+        .enable_all()
+        .build()
+        .expect("Failed to start the async runtime");
+
     // Create a new crawler
-    let starting_url = Url::parse(&config.starting_url).expect("Failed to parse starting URL");
-    let pool: Arc<ThreadPool> = Arc::new(ThreadPoolBuilder::new().num_threads(config.max_threads).build().unwrap());
-    let crawler = crawl::Crawler::new(config, conn, pool);
+    let seeds = tools::get_seeds(&config);
+    if seeds.is_empty() {
+        eprintln!("No valid seeds or starting URL to crawl from.");
+        return;
+    }
+    let seed_count = seeds.len();
+    let client = http::build_client(&config).expect("Failed to build the HTTP client");
+    let crawler = Arc::new(crawl::Crawler::new(config, conn, client, &seeds));
+    let crawler_clone = Arc::clone(&crawler);
 
     // Start crawling, with a timeout.
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(move || {
-        crawler.timed_crawl_website(starting_url);
-        tx.send(()).ok();
-    });
+    runtime.block_on(async {
+        let mut crawl = tokio::spawn(async move {
+            if resume {
+                crawler.timed_resume_crawl(seeds).await;
+            } else {
+                crawler.timed_crawl_website(seeds).await;
+            }
+        });
 
-    loop {
         // Wait for the job to complete, or a timeout.
-        match rx.recv_timeout(Duration::from_secs(config_clone.crawler_timeout)) {
-            Ok(()) => {
+        match tokio::time::timeout(Duration::from_secs(config_clone.crawler_timeout), &mut crawl).await {
+            Ok(Ok(())) => {
                 println!("Crawler thread finished successfully.");
-                break;
             }
-            Err(RecvTimeoutError::Timeout) => {
-                eprintln!("Crawler thread timed-out after {:?} seconds. Aborting...", config_clone.crawler_timeout);
-                break;
+            Ok(Err(e)) => {
+                eprintln!("Crawler thread failed: {}. Aborting...", e);
             }
-            Err(RecvTimeoutError::Disconnected) => {
-                eprintln!("Crawler thread disconnected. Aborting...");
-                break;
+            Err(_) => {
+                eprintln!("Crawler thread timed-out after {:?} seconds. Aborting...", config_clone.crawler_timeout);
+                crawl.abort();
             }
         }
-    }
+    });
+    runtime.shutdown_background();
 
     // Print the number of URLs visited
     println!("Visited {} URLs.", data::URLS_VISITED.load(std::sync::atomic::Ordering::SeqCst));
+    let tripped = crawler_clone.tripped_domains();
+    if !tripped.is_empty() {
+        println!("Circuit breaker tripped for {} hosts:", tripped.len());
+        for (host, trips) in tripped {
+            println!("  {} ({} times)", host, trips);
+        }
+    }
     if config_clone.sqlite_enabled {
         println!("DB Contains {:?} URLs, {:?} complete.", sqlite::connect_and_get_total_rows(&config_clone).unwrap(), sqlite::connect_and_get_completed_rows(&config_clone).unwrap());
+        if seed_count > 1 && !config_clone.list_mode {
+            let seed_stats = sqlite::connect_and_get_seed_stats(&config_clone).unwrap_or_default();
+            println!("Results for {} seeds:", seed_stats.len());
+            for stats in seed_stats {
+                println!("  {}: {} discovered, {} fetched, {} failed, {} pending", stats.seed, stats.discovered, stats.fetched, stats.failed, stats.pending);
+            }
+        }
+        let pending = sqlite::connect_and_get_pending_frontier_rows(&config_clone).unwrap_or(0);
+        if pending > 0 {
+            println!("{} URLs remain in the frontier, run with --resume to continue.", pending);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use reqwest::{Client, Url};
+use rusqlite::Connection;
+
+use crate::config;
+use crate::robots;
+use crate::tools;
+
+// Spaces out requests to the same host, while letting different hosts proceed in parallel.
+// Each host gets a minimum interval of CRAWLER_REQUEST_DELAY_MS, or the robots.txt Crawl-delay if that is longer.
+// With AUTO_THROTTLE on, CRAWLER_REQUEST_DELAY_MS is only the starting point, and the interval follows the host's latency.
+pub(crate) struct PolitenessScheduler {
+    default_delay: Duration,
+    max_crawl_delay: Duration,
+    auto_throttle: Option<AutoThrottle>,
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+// The bounds and current per-host delays for adaptive throttling.
+struct AutoThrottle {
+    min_delay: Duration,
+    max_delay: Duration,
+    target_concurrency: f64,
+    host_delays: Mutex<HashMap<String, Duration>>,
+}
+
+impl AutoThrottle {
+    // Work out the next delay for a host from its current delay and the latest response.
+    // Fast responses pull the delay towards latency / target concurrency, and errors double it.
+    fn next_delay(&self, current: Duration, latency: Duration, success: bool) -> Duration {
+        let target = latency.div_f64(self.target_concurrency);
+        let delay = if success {
+            (current + target) / 2
+        } else {
+            current.saturating_mul(2).max(target)
+        };
+        delay.clamp(self.min_delay, self.max_delay)
+    }
+}
+
+impl PolitenessScheduler {
+    pub fn new(config: &config::Config) -> Self {
+        let auto_throttle = config.auto_throttle.then(|| {
+            let min_delay = Duration::from_millis(config.auto_throttle_min_delay_ms);
+            AutoThrottle {
+                min_delay,
+                max_delay: Duration::from_millis(config.auto_throttle_max_delay_ms).max(min_delay),
+                target_concurrency: if config.auto_throttle_target_concurrency > 0.0 { config.auto_throttle_target_concurrency } else { 1.0 },
+                host_delays: Mutex::new(HashMap::new()),
+            }
+        });
+        PolitenessScheduler {
+            default_delay: Duration::from_millis(config.crawler_request_delay_ms),
+            max_crawl_delay: Duration::from_millis(config.max_crawl_delay_ms),
+            auto_throttle,
+            next_request: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Get the minimum interval between requests to this host, including its robots.txt Crawl-delay.
+    pub async fn delay_for(&self, config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> Duration {
+        let host = url.host_str().unwrap_or("");
+        let robots_delay = self.robots_delay_for(config, client, db_conn, url).await;
+        match &self.auto_throttle {
+            Some(auto_throttle) => self.current_delay(auto_throttle, host).max(robots_delay),
+            None => self.default_delay.max(robots_delay),
+        }
+    }
+
+    // Get the Crawl-delay from the host's current robots.txt, capped at MAX_CRAWL_DELAY_MS. Zero if there isn't one.
+    // It's read from the robots.txt cache each time, so a changed Crawl-delay is picked up when the robots.txt is fetched again.
+    async fn robots_delay_for(&self, config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> Duration {
+        if !config.respect_robots {
+            return Duration::ZERO;
+        }
+        match robots::get_crawl_delay(config, client, db_conn, url).await {
+            Some(crawl_delay) => crawl_delay.min(self.max_crawl_delay),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn current_delay(&self, auto_throttle: &AutoThrottle, host: &str) -> Duration {
+        auto_throttle.host_delays.lock().unwrap().get(host).copied()
+            .unwrap_or_else(|| self.default_delay.clamp(auto_throttle.min_delay, auto_throttle.max_delay))
+    }
+
+    // Feed a response back into the adaptive throttle. Does nothing unless AUTO_THROTTLE is on.
+    pub fn record_response(&self, config: &config::Config, host: &str, latency: Duration, success: bool) {
+        let auto_throttle = match &self.auto_throttle {
+            Some(auto_throttle) => auto_throttle,
+            None => return,
+        };
+        let current = self.current_delay(auto_throttle, host);
+        let delay = auto_throttle.next_delay(current, latency, success);
+        auto_throttle.host_delays.lock().unwrap().insert(host.to_string(), delay);
+        if delay != current {
+            tools::debug_log(config.debug, &format!("AutoThrottle delay for {} is now {:?} (latency {:?}, success {})", host, delay, latency, success));
+        }
+    }
+
+    // Reserve the next request slot for this host, and return how long we have to wait for it.
+    pub fn reserve(&self, host: &str, delay: Duration) -> Duration {
+        let now = Instant::now();
+        let mut next_request = self.next_request.lock().unwrap();
+        let start = next_request.get(host).copied().unwrap_or(now).max(now);
+        next_request.insert(host.to_string(), start + delay);
+        start - now
+    }
+
+    // Get how long until this host's next request slot is free. Zero if it's free now.
+    pub fn time_until_turn(&self, host: &str) -> Duration {
+        self.next_request.lock().unwrap().get(host)
+            .map(|next| next.saturating_duration_since(Instant::now()))
+            .unwrap_or_default()
+    }
+
+    // Reserve this host's next request slot if it's free now. Returns false, without reserving it, if the host isn't ready yet.
+    pub fn try_reserve(&self, host: &str, delay: Duration) -> bool {
+        let now = Instant::now();
+        let mut next_request = self.next_request.lock().unwrap();
+        if next_request.get(host).is_some_and(|next| *next > now) {
+            return false;
+        }
+        next_request.insert(host.to_string(), now + delay);
+        true
+    }
+
+    // Take this host's turn if it's ready now, instead of waiting for it.
+    pub async fn try_take_turn(&self, config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> bool {
+        let delay = self.delay_for(config, client, db_conn, url).await;
+        self.try_reserve(url.host_str().unwrap_or(""), delay)
+    }
+
+    // Wait until this host is ready for another request.
+    pub async fn wait_for_turn(&self, config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) {
+        let delay = self.delay_for(config, client, db_conn, url).await;
+        let wait = self.reserve(url.host_str().unwrap_or(""), delay);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> PolitenessScheduler {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.crawler_request_delay_ms = 1000;
+        config.auto_throttle = true;
+        config.auto_throttle_min_delay_ms = 100;
+        config.auto_throttle_max_delay_ms = 5000;
+        config.auto_throttle_target_concurrency = 1.0;
+        PolitenessScheduler::new(&config)
+    }
+
+    fn current(scheduler: &PolitenessScheduler, host: &str) -> Duration {
+        scheduler.current_delay(scheduler.auto_throttle.as_ref().unwrap(), host)
+    }
+
+    #[test]
+    fn test_auto_throttle_falls_for_fast_hosts() {
+        let scheduler = scheduler();
+        let config = config::Config::new("crab.json".to_string());
+        assert_eq!(current(&scheduler, "fast.com"), Duration::from_millis(1000));
+        scheduler.record_response(&config, "fast.com", Duration::from_millis(50), true);
+        assert_eq!(current(&scheduler, "fast.com"), Duration::from_millis(525));
+        for _ in 0..20 {
+            scheduler.record_response(&config, "fast.com", Duration::from_millis(50), true);
+        }
+        assert_eq!(current(&scheduler, "fast.com"), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_auto_throttle_rises_with_latency_and_errors() {
+        let scheduler = scheduler();
+        let config = config::Config::new("crab.json".to_string());
+        scheduler.record_response(&config, "slow.com", Duration::from_millis(3000), true);
+        assert_eq!(current(&scheduler, "slow.com"), Duration::from_millis(2000));
+        scheduler.record_response(&config, "slow.com", Duration::from_millis(10), false);
+        assert_eq!(current(&scheduler, "slow.com"), Duration::from_millis(4000));
+        scheduler.record_response(&config, "slow.com", Duration::from_millis(10), false);
+        assert_eq!(current(&scheduler, "slow.com"), Duration::from_millis(5000));
+        assert_eq!(current(&scheduler, "other.com"), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_reserve_spaces_requests_per_host() {
+        let scheduler = scheduler();
+        assert!(scheduler.reserve("a.com", Duration::from_secs(10)).is_zero());
+        assert!(scheduler.reserve("a.com", Duration::from_secs(10)) > Duration::from_secs(9));
+        assert!(scheduler.reserve("b.com", Duration::from_secs(10)).is_zero());
+    }
+
+    #[test]
+    fn test_try_reserve_leaves_a_busy_host_alone() {
+        let scheduler = scheduler();
+        assert!(scheduler.time_until_turn("a.com").is_zero());
+        assert!(scheduler.try_reserve("a.com", Duration::from_secs(10)));
+        assert!(scheduler.time_until_turn("a.com") > Duration::from_secs(9));
+        assert!(!scheduler.try_reserve("a.com", Duration::from_secs(10)));
+        assert!(scheduler.time_until_turn("a.com") <= Duration::from_secs(10));
+        assert!(scheduler.try_reserve("b.com", Duration::ZERO));
+        assert!(scheduler.try_reserve("b.com", Duration::ZERO));
+    }
+}
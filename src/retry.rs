@@ -0,0 +1,148 @@
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::config;
+use crate::http::FetchError;
+
+// Decides if a failed fetch should be tried again, and how long to wait first.
+pub(crate) struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    status_codes: Vec<u16>,
+    error_kinds: Vec<String>,
+}
+
+impl RetryPolicy {
+    pub fn new(config: &config::Config) -> Self {
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts.max(1),
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            status_codes: config.retry_status_codes.clone(),
+            error_kinds: config.retry_error_kinds.iter().map(|kind| kind.to_lowercase()).collect(),
+        }
+    }
+
+    // Check if this error is worth another attempt.
+    pub fn is_retryable(&self, error: &FetchError) -> bool {
+        match error {
+            FetchError::Status(info) => info.status_code.is_some_and(|status| self.status_codes.contains(&status)),
+            FetchError::Request(e) => {
+                let kind = if e.is_timeout() {
+                    "timeout"
+                } else if e.is_connect() {
+                    "connect"
+                } else if e.is_body() || e.is_decode() {
+                    "body"
+                } else {
+                    "request"
+                };
+                self.error_kinds.iter().any(|k| k == kind)
+            }
+            FetchError::TooLarge(..) | FetchError::NotHtml(_) => false,
+        }
+    }
+
+    // Get how long to wait before the next attempt, or None if we should give up.
+    // Attempts are numbered from 1.
+    pub fn next_delay(&self, attempt: u32, error: &FetchError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.is_retryable(error) {
+            return None;
+        }
+        let backoff = self.backoff(attempt);
+
+        // Honour the server's Retry-After on 429 and 503. If it asks us to wait longer than RETRY_MAX_DELAY_MS, give up rather than retry early.
+        if let FetchError::Status(info) = error {
+            if matches!(info.status_code, Some(429) | Some(503)) {
+                if let Some(retry_after) = info.retry_after {
+                    if retry_after > self.max_delay {
+                        return None;
+                    }
+                    return Some(retry_after.max(backoff));
+                }
+            }
+        }
+        Some(backoff)
+    }
+
+    // Exponential backoff with jitter, between half and all of base * 2^(attempt - 1).
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let delay = exponential.min(self.max_delay);
+        if delay.is_zero() {
+            return delay;
+        }
+        let half = delay / 2;
+        half + Duration::from_millis(rand::thread_rng().gen_range(0..=(delay - half).as_millis() as u64))
+    }
+}
+
+// Parse a Retry-After header, which is either a number of seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.with_timezone(&Utc).signed_duration_since(Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Url;
+    use crate::data;
+
+    fn status_error(status: u16, retry_after: Option<Duration>) -> FetchError {
+        let mut info = data::ResponseInfo::new(Url::parse("https://www.example.com").unwrap());
+        info.status_code = Some(status);
+        info.retry_after = retry_after;
+        FetchError::Status(Box::new(info))
+    }
+
+    fn policy() -> RetryPolicy {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.retry_max_attempts = 3;
+        config.retry_base_delay_ms = 100;
+        config.retry_max_delay_ms = 1000;
+        RetryPolicy::new(&config)
+    }
+
+    #[test]
+    fn test_retryable_status_codes() {
+        let policy = policy();
+        assert!(policy.is_retryable(&status_error(503, None)));
+        assert!(policy.is_retryable(&status_error(429, None)));
+        assert!(!policy.is_retryable(&status_error(404, None)));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_gives_up() {
+        let policy = policy();
+        let error = status_error(500, None);
+        let first = policy.next_delay(1, &error).unwrap();
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let second = policy.next_delay(2, &error).unwrap();
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+        assert!(policy.next_delay(3, &error).is_none());
+        assert!(policy.next_delay(1, &status_error(404, None)).is_none());
+    }
+
+    #[test]
+    fn test_retry_after_is_honoured_or_given_up() {
+        let policy = policy();
+        assert_eq!(policy.next_delay(1, &status_error(429, Some(Duration::from_millis(700)))), Some(Duration::from_millis(700)));
+        assert_eq!(policy.next_delay(1, &status_error(503, Some(Duration::from_millis(1000)))), Some(Duration::from_millis(1000)));
+        assert_eq!(policy.next_delay(1, &status_error(503, Some(Duration::from_secs(60)))), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
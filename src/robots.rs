@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use reqwest::{Client, Url};
+use rusqlite::Connection;
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::config;
+use crate::http;
+use crate::sqlite;
+use crate::tools;
+
+// X-Robots-Tag directives that take a value after a colon, so the colon doesn't name a user agent.
+const VALUE_DIRECTIVES: [&str; 4] = ["unavailable_after", "max-snippet", "max-image-preview", "max-video-preview"];
+
+// How long an unreachable robots.txt keeps its host disallowed before we try to fetch it again.
+const ROBOTS_UNREACHABLE_RETRY: Duration = Duration::from_secs(300);
+
+// What a robots.txt lets us crawl, following the fetch rules in RFC 9309 section 2.3.1.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RobotsTxt {
+    // It was fetched, and these are its rules.
+    Rules(String),
+    // It's unavailable (4xx), so there are no restrictions.
+    AllowAll,
+    // It's unreachable (5xx, 429 or a network error), so everything is disallowed for now.
+    Unreachable,
+}
+
+impl RobotsTxt {
+    // Work out what a robots.txt response means from its status code. None means the request failed.
+    // 429 is treated as unreachable rather than unavailable, since the host is asking us to back off.
+    pub fn from_response(status_code: Option<u16>, body: String) -> Self {
+        match status_code {
+            Some(200..=299) => RobotsTxt::Rules(body),
+            Some(429) => RobotsTxt::Unreachable,
+            // Running out of redirects counts as unavailable.
+            Some(300..=499) => RobotsTxt::AllowAll,
+            _ => RobotsTxt::Unreachable,
+        }
+    }
+
+    // The rules to match against, which are empty unless the robots.txt was fetched.
+    pub fn body(&self) -> &str {
+        match self {
+            RobotsTxt::Rules(body) => body,
+            _ => "",
+        }
+    }
+}
+
+// The indexing directives a page gives crawlers, in <meta name="robots"> tags and X-Robots-Tag headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct RobotsDirectives {
+    // Don't store the page's content.
+    pub(crate) noindex: bool,
+    // Don't follow the page's links.
+    pub(crate) nofollow: bool,
+}
+
+impl RobotsDirectives {
+    // Read a page's directives from its X-Robots-Tag header values and <meta name> tags.
+    // Only directives for every crawler, or ones naming ROBOTS_USER_AGENT, count.
+    pub fn from_page(header_values: &[String], meta_tags: &[(String, String)], robots_user_agent: &str) -> Self {
+        let mut directives = RobotsDirectives::default();
+        for value in header_values {
+            directives.add(value, robots_user_agent);
+        }
+        for (name, content) in meta_tags {
+            if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case(robots_user_agent) {
+                directives.add(content, robots_user_agent);
+            }
+        }
+        directives
+    }
+
+    // Add a comma separated list of directives. In a header, "otherbot: noindex, nofollow" applies both to otherbot only.
+    fn add(&mut self, value: &str, robots_user_agent: &str) {
+        let mut user_agent: Option<String> = None;
+        for part in value.split(',') {
+            let mut directive = part.trim();
+            if let Some((name, rest)) = directive.split_once(':') {
+                if !VALUE_DIRECTIVES.contains(&name.trim().to_lowercase().as_str()) {
+                    user_agent = Some(name.trim().to_string());
+                    directive = rest.trim();
+                }
+            }
+            if let Some(user_agent) = &user_agent {
+                if !tools::is_robots_user_agent(user_agent, robots_user_agent) {
+                    continue;
+                }
+            }
+            match directive.to_lowercase().as_str() {
+                "noindex" => self.noindex = true,
+                "nofollow" => self.nofollow = true,
+                "none" => {
+                    self.noindex = true;
+                    self.nofollow = true;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // How the directives are recorded in visited, or None if there aren't any.
+    pub fn as_str(&self) -> Option<&'static str> {
+        match (self.noindex, self.nofollow) {
+            (true, true) => Some("noindex,nofollow"),
+            (true, false) => Some("noindex"),
+            (false, true) => Some("nofollow"),
+            (false, false) => None,
+        }
+    }
+}
+
+// A robots.txt in the cache, with the Crawl-delay it sets for our robots user agent.
+#[derive(Clone)]
+struct CachedRobotsTxt {
+    robots_txt: RobotsTxt,
+    crawl_delay: Option<Duration>,
+    expires_at: Instant,
+}
+
+lazy_static! {
+    // The robots.txt for each origin we've looked up this run.
+    static ref ROBOTS_CACHE: Mutex<HashMap<String, CachedRobotsTxt>> = Mutex::new(HashMap::new());
+    // A lock for each origin, held while its robots.txt is loaded, so concurrent lookups wait for one fetch instead of each making their own.
+    static ref ROBOTS_LOOKUPS: Mutex<HashMap<String, Arc<AsyncMutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+// Get the robots.txt for a URL's origin, its scheme, host and port.
+// It's read from memory, then SQLite, and only fetched if neither has a copy younger than ROBOTS_CACHE_TTL_SECS.
+// While a robots.txt is unreachable, an expired copy is used if we have one.
+pub(crate) async fn get_robots_txt(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> RobotsTxt {
+    lookup_robots_txt(config, client, db_conn, url).await.robots_txt
+}
+
+// Get the Crawl-delay that the origin's current robots.txt sets for our robots user agent, if there is one.
+pub(crate) async fn get_crawl_delay(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> Option<Duration> {
+    lookup_robots_txt(config, client, db_conn, url).await.crawl_delay
+}
+
+async fn lookup_robots_txt(config: &config::Config, client: &Client, db_conn: &Arc<Mutex<Connection>>, url: &Url) -> CachedRobotsTxt {
+    let origin = url.origin().ascii_serialization();
+    if let Some(cached) = fresh_robots_txt(&origin) {
+        return cached;
+    }
+
+    // Only one task loads an origin's robots.txt at a time. Any others wait, and then find it in the cache.
+    let lookup = ROBOTS_LOOKUPS.lock().unwrap().entry(origin.clone()).or_default().clone();
+    let _lookup = lookup.lock().await;
+    if let Some(cached) = fresh_robots_txt(&origin) {
+        return cached;
+    }
+    let ttl = Duration::from_secs(config.robots_cache_ttl_secs);
+    let mut expired = ROBOTS_CACHE.lock().unwrap().get(&origin).map(|cached| cached.robots_txt.clone());
+
+    // A previous run may have stored a copy that's still fresh.
+    match sqlite::get_robots_txt(&db_conn.lock().unwrap(), &origin) {
+        Ok(Some(stored)) => {
+            let robots_txt = RobotsTxt::from_response(stored.status_code, stored.body);
+            let age = Duration::from_secs(stored.age_secs);
+            if age < ttl {
+                return cache_robots_txt(config, &origin, robots_txt, ttl - age);
+            }
+            expired = expired.or(Some(robots_txt));
+        }
+        Ok(None) => (),
+        Err(e) => tools::debug_log(config.debug, &format!("Failed to load robots.txt for {} from SQLite: {}", origin, e)),
+    }
+
+    let fetched = match url.join("/robots.txt") {
+        Ok(robots_url) => http::fetch_robots_txt(config, client, db_conn, &robots_url).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let (status_code, body) = match fetched {
+        Ok((status_code, body)) => (Some(status_code), truncate_robots_txt(body, config.robots_max_bytes)),
+        Err(e) => {
+            tools::debug_log(config.debug, &format!("Failed to fetch robots.txt for {}: {}", origin, e));
+            (None, String::new())
+        }
+    };
+    let robots_txt = RobotsTxt::from_response(status_code, body);
+    if robots_txt == RobotsTxt::Unreachable {
+        let robots_txt = match expired {
+            Some(expired) if expired != RobotsTxt::Unreachable => {
+                tools::debug_log(config.debug, &format!("robots.txt for {} is unreachable, using the expired copy", origin));
+                expired
+            }
+            _ => {
+                tools::debug_log(config.debug, &format!("robots.txt for {} is unreachable, disallowing it for {:?}", origin, ROBOTS_UNREACHABLE_RETRY.min(ttl)));
+                RobotsTxt::Unreachable
+            }
+        };
+        return cache_robots_txt(config, &origin, robots_txt, ROBOTS_UNREACHABLE_RETRY.min(ttl));
+    }
+
+    if let Err(e) = sqlite::upsert_robots_txt(&db_conn.lock().unwrap(), &origin, status_code, robots_txt.body()) {
+        tools::debug_log(config.debug, &format!("Failed to store robots.txt for {} in SQLite: {}", origin, e));
+    }
+    cache_robots_txt(config, &origin, robots_txt, ttl)
+}
+
+// Get an origin's robots.txt from memory, if it hasn't expired.
+fn fresh_robots_txt(origin: &str) -> Option<CachedRobotsTxt> {
+    ROBOTS_CACHE.lock().unwrap().get(origin).filter(|cached| cached.expires_at > Instant::now()).cloned()
+}
+
+// Store a robots.txt in memory, along with its Crawl-delay, so it's only parsed once each time it's loaded.
+fn cache_robots_txt(config: &config::Config, origin: &str, robots_txt: RobotsTxt, ttl: Duration) -> CachedRobotsTxt {
+    let crawl_delay = tools::parse_crawl_delay(robots_txt.body(), &config.robots_user_agent);
+    if let Some(crawl_delay) = crawl_delay {
+        tools::debug_log(config.debug, &format!("robots.txt for {} sets a Crawl-delay of {:?}", origin, crawl_delay));
+    }
+    let cached = CachedRobotsTxt { robots_txt, crawl_delay, expires_at: Instant::now() + ttl };
+    ROBOTS_CACHE.lock().unwrap().insert(origin.to_string(), cached.clone());
+    cached
+}
+
+// Cut a robots.txt down to ROBOTS_MAX_BYTES, dropping any line the limit splits.
+// RFC 9309 asks crawlers to parse at least the first 500 KiB.
+fn truncate_robots_txt(mut body: String, max_bytes: usize) -> String {
+    if max_bytes == 0 || body.len() <= max_bytes {
+        return body;
+    }
+    let mut end = max_bytes;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    body.truncate(body[..end].rfind('\n').map(|newline| newline + 1).unwrap_or(0));
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        let body = "User-agent: *\nDisallow: /".to_string();
+        assert_eq!(RobotsTxt::from_response(Some(200), body.clone()), RobotsTxt::Rules(body.clone()));
+        assert_eq!(RobotsTxt::from_response(Some(404), body.clone()), RobotsTxt::AllowAll);
+        assert_eq!(RobotsTxt::from_response(Some(403), body.clone()), RobotsTxt::AllowAll);
+        assert_eq!(RobotsTxt::from_response(Some(301), body.clone()), RobotsTxt::AllowAll);
+        assert_eq!(RobotsTxt::from_response(Some(429), body.clone()), RobotsTxt::Unreachable);
+        assert_eq!(RobotsTxt::from_response(Some(503), body.clone()), RobotsTxt::Unreachable);
+        assert_eq!(RobotsTxt::from_response(None, String::new()), RobotsTxt::Unreachable);
+        assert_eq!(RobotsTxt::AllowAll.body(), "");
+    }
+
+    #[test]
+    fn test_robots_directives() {
+        let meta = |name: &str, content: &str| vec![(name.to_string(), content.to_string())];
+        let header = |value: &str| vec![value.to_string()];
+
+        let directives = RobotsDirectives::from_page(&[], &meta("Robots", "NoIndex, follow"), "crab-crawler");
+        assert_eq!(directives, RobotsDirectives { noindex: true, nofollow: false });
+        assert_eq!(directives.as_str(), Some("noindex"));
+        assert_eq!(RobotsDirectives::from_page(&[], &meta("crab-crawler", "none"), "crab-crawler").as_str(), Some("noindex,nofollow"));
+        assert_eq!(RobotsDirectives::from_page(&[], &meta("googlebot", "noindex"), "crab-crawler").as_str(), None);
+        assert_eq!(RobotsDirectives::from_page(&[], &meta("description", "noindex"), "crab-crawler").as_str(), None);
+
+        assert_eq!(RobotsDirectives::from_page(&header("nofollow, max-snippet: 20"), &[], "crab-crawler").as_str(), Some("nofollow"));
+        assert_eq!(RobotsDirectives::from_page(&header("googlebot: noindex, nofollow"), &[], "crab-crawler").as_str(), None);
+        assert_eq!(RobotsDirectives::from_page(&header("googlebot: noindex, crab-crawler: nofollow"), &[], "crab-crawler").as_str(), Some("nofollow"));
+        assert_eq!(RobotsDirectives::from_page(&header("unavailable_after: 25 Jun 2010 15:00:00 PST"), &[], "crab-crawler").as_str(), None);
+    }
+
+    #[test]
+    fn test_truncate_robots_txt() {
+        let body = "User-agent: *\nDisallow: /private\nDisallow: /tmp\n".to_string();
+        assert_eq!(truncate_robots_txt(body.clone(), 0), body);
+        assert_eq!(truncate_robots_txt(body.clone(), 1000), body);
+        assert_eq!(truncate_robots_txt(body.clone(), 40), "User-agent: *\nDisallow: /private\n");
+        assert_eq!(truncate_robots_txt(body, 5), "");
+    }
+
+    #[tokio::test]
+    async fn test_stored_robots_txt_is_reused() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.sqlite_enabled = false;
+        let conn = sqlite::connect_sqlite_and_migrate(&config).unwrap().unwrap();
+        sqlite::upsert_robots_txt(&conn, "http://stored.invalid", Some(200), "User-agent: *\nDisallow: /").unwrap();
+        sqlite::upsert_robots_txt(&conn, "http://missing.invalid", Some(404), "").unwrap();
+        let db_conn = Arc::new(Mutex::new(conn));
+        let client = Client::new();
+
+        let stored = get_robots_txt(&config, &client, &db_conn, &Url::parse("http://stored.invalid/page").unwrap()).await;
+        assert_eq!(stored, RobotsTxt::Rules("User-agent: *\nDisallow: /".to_string()));
+        let missing = get_robots_txt(&config, &client, &db_conn, &Url::parse("http://missing.invalid/").unwrap()).await;
+        assert_eq!(missing, RobotsTxt::AllowAll);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_lookups_fetch_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A slow server that counts how many times robots.txt is requested.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server_requests = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                server_requests.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = [0; 1024];
+                    let _ = socket.read(&mut request).await;
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    let body = "User-agent: *\nCrawl-delay: 2\n";
+                    let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        let mut config = config::Config::new("crab.json".to_string());
+        config.sqlite_enabled = false;
+        let db_conn = Arc::new(Mutex::new(sqlite::connect_sqlite_and_migrate(&config).unwrap().unwrap()));
+        let client = Client::new();
+        let url = Url::parse(&format!("http://{}/page", address)).unwrap();
+        let lookups = (0..5).map(|_| get_robots_txt(&config, &client, &db_conn, &url));
+        for robots_txt in futures::future::join_all(lookups).await {
+            assert_eq!(robots_txt.body(), "User-agent: *\nCrawl-delay: 2\n");
+        }
+        assert_eq!(get_crawl_delay(&config, &client, &db_conn, &url).await, Some(Duration::from_secs(2)));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
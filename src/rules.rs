@@ -0,0 +1,170 @@
+use regex::Regex;
+use reqwest::Url;
+
+use crate::config;
+use crate::tools;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuleAction {
+    Allow,
+    Deny,
+}
+
+// What part of the URL a rule is matched against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuleTarget {
+    Url,
+    Path,
+    FileName,
+}
+
+struct UrlRule {
+    action: RuleAction,
+    target: RuleTarget,
+    re: Regex,
+    description: String,
+}
+
+// Ordered allow and deny rules for URLs. The first matching rule decides, and URL_RULES_DEFAULT decides the rest.
+// Glob patterns starting with / match the path, patterns with :// match the whole URL, and anything else matches the file name.
+// In globs, * matches within a path segment and ** matches across them. Regex patterns search the whole URL.
+pub(crate) struct UrlRules {
+    rules: Vec<UrlRule>,
+    default_allow: bool,
+}
+
+impl UrlRules {
+    pub fn new(config: &config::Config) -> Self {
+        let rules = config.url_rules.iter().filter_map(|rule| {
+            let action = match rule.action.trim().to_lowercase().as_str() {
+                "allow" => RuleAction::Allow,
+                "deny" => RuleAction::Deny,
+                _ => {
+                    tools::debug_log(config.debug, &format!("Ignoring URL rule with unknown action {}", rule.action));
+                    return None;
+                }
+            };
+            let (target, pattern, description) = match (&rule.glob, &rule.regex) {
+                (Some(glob), None) => {
+                    let target = if glob.starts_with('/') {
+                        RuleTarget::Path
+                    } else if glob.contains("://") {
+                        RuleTarget::Url
+                    } else {
+                        RuleTarget::FileName
+                    };
+                    (target, glob_to_regex(glob), format!("{} glob {}", rule.action, glob))
+                }
+                (None, Some(regex)) => (RuleTarget::Url, regex.clone(), format!("{} regex {}", rule.action, regex)),
+                _ => {
+                    tools::debug_log(config.debug, &format!("Ignoring {} URL rule, it needs exactly one of glob or regex", rule.action));
+                    return None;
+                }
+            };
+            match Regex::new(&pattern) {
+                Ok(re) => Some(UrlRule { action, target, re, description: description.to_lowercase() }),
+                Err(e) => {
+                    tools::debug_log(config.debug, &format!("Ignoring invalid URL rule {}: {}", description, e));
+                    None
+                }
+            }
+        }).collect();
+        let default_allow = !config.url_rules_default.trim().eq_ignore_ascii_case("deny");
+        UrlRules { rules, default_allow }
+    }
+
+    // Check a URL against the rules. If it's rejected, returns the rule that rejected it.
+    pub fn check(&self, url: &Url) -> Result<(), String> {
+        for rule in self.rules.iter() {
+            let target = match rule.target {
+                RuleTarget::Url => url.as_str(),
+                RuleTarget::Path => url.path(),
+                RuleTarget::FileName => url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or(""),
+            };
+            if rule.re.is_match(target) {
+                return match rule.action {
+                    RuleAction::Allow => Ok(()),
+                    RuleAction::Deny => Err(rule.description.clone()),
+                };
+            }
+        }
+        if self.default_allow {
+            Ok(())
+        } else {
+            Err("default deny".to_string())
+        }
+    }
+}
+
+// Turn a glob into an anchored regex.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: &str, glob: Option<&str>, regex: Option<&str>) -> config::UrlRuleConfig {
+        config::UrlRuleConfig { action: action.to_string(), glob: glob.map(|g| g.to_string()), regex: regex.map(|r| r.to_string()) }
+    }
+
+    fn check(rules: &UrlRules, url: &str) -> Result<(), String> {
+        rules.check(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.url_rules = vec![
+            rule("deny", Some("/login"), None),
+            rule("deny", Some("*.zip"), None),
+            rule("allow", Some("/news/**"), None),
+            rule("deny", None, Some(r"[?&]sort=")),
+        ];
+        config.url_rules_default = "deny".to_string();
+        let rules = UrlRules::new(&config);
+        assert_eq!(check(&rules, "https://www.example.com/news/2024/story"), Ok(()));
+        assert_eq!(check(&rules, "https://www.example.com/news/archive.zip"), Err("deny glob *.zip".to_string()));
+        assert_eq!(check(&rules, "https://www.example.com/login"), Err("deny glob /login".to_string()));
+        assert_eq!(check(&rules, "https://www.example.com/login/help"), Err("default deny".to_string()));
+        assert_eq!(check(&rules, "https://www.example.com/sport?sort=asc"), Err("deny regex [?&]sort=".to_string()));
+    }
+
+    #[test]
+    fn test_default_allow_and_invalid_rules() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.url_rules = vec![
+            rule("deny", Some("https://*.example.com/private/*"), None),
+            rule("deny", None, Some("(")),
+            rule("maybe", Some("/anything"), None),
+            rule("deny", Some("/both"), Some("both")),
+        ];
+        let rules = UrlRules::new(&config);
+        assert_eq!(rules.rules.len(), 1);
+        assert!(check(&rules, "https://www.example.com/private/page").is_err());
+        assert_eq!(check(&rules, "https://www.example.com/private/a/page"), Ok(()));
+        assert_eq!(check(&rules, "https://www.example.com/anything"), Ok(()));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(glob_to_regex("/news/**"), "^/news/.*$");
+        assert_eq!(glob_to_regex("*.zip"), r"^[^/]*\.zip$");
+        assert_eq!(glob_to_regex("/page?"), "^/page[^/]$");
+    }
+}
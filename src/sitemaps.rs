@@ -0,0 +1,193 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::Mutex;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+
+use crate::data;
+
+// The sitemaps protocol caps a sitemap at 50MB uncompressed.
+pub(crate) const SITEMAP_MAX_BYTES: u64 = 50 * 1024 * 1024;
+// How many levels of sitemap index we follow below the sitemaps we discover.
+pub(crate) const SITEMAP_MAX_NESTING: usize = 3;
+
+lazy_static! {
+    static ref SITEMAP_ENTRY: Regex = Regex::new(r"(?is)<(?:url|sitemap)\b[^>]*>(.*?)</(?:url|sitemap)>").unwrap();
+    static ref SITEMAP_LOC: Regex = Regex::new(r"(?is)<loc\b[^>]*>(.*?)</loc>").unwrap();
+    static ref SITEMAP_LASTMOD: Regex = Regex::new(r"(?is)<lastmod\b[^>]*>(.*?)</lastmod>").unwrap();
+    static ref SITEMAP_PRIORITY: Regex = Regex::new(r"(?is)<priority\b[^>]*>(.*?)</priority>").unwrap();
+}
+
+// What a sitemap file turned out to hold.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Sitemap {
+    // A <sitemapindex>, pointing at more sitemaps.
+    Index(Vec<Url>),
+    // A <urlset> of pages.
+    UrlSet(Vec<data::SitemapUrl>),
+}
+impl Sitemap {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Sitemap::Index(_) => "index",
+            Sitemap::UrlSet(_) => "urlset",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Sitemap::Index(sitemaps) => sitemaps.len(),
+            Sitemap::UrlSet(urls) => urls.len(),
+        }
+    }
+}
+
+// Remembers which hosts we've already looked for sitemaps on, so each host's are only fetched once per run.
+pub(crate) struct SitemapDiscovery {
+    hosts: Mutex<HashSet<String>>,
+}
+
+impl SitemapDiscovery {
+    pub fn new() -> Self {
+        SitemapDiscovery { hosts: Mutex::new(HashSet::new()) }
+    }
+
+    // Check if this host's sitemaps still need discovering, and claim them if so.
+    pub fn claim_host(&self, host: &str) -> bool {
+        self.hosts.lock().unwrap().insert(host.to_lowercase())
+    }
+}
+
+// Get the sitemaps a robots.txt file points to with Sitemap: lines.
+pub(crate) fn parse_robots_sitemaps(robots_txt: &str) -> Vec<Url> {
+    robots_txt.lines().filter_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("sitemap") {
+            return None;
+        }
+        Url::parse(value.split('#').next().unwrap_or("").trim()).ok()
+    }).collect()
+}
+
+// Turn a fetched sitemap or feed into text, unzipping it first if it's gzip-compressed (.xml.gz).
+// Anything over the sitemap protocol's 50MB limit is rejected.
+pub(crate) fn decode_xml(bytes: &[u8]) -> Result<String, String> {
+    let mut decoded = Vec::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes).take(SITEMAP_MAX_BYTES + 1).read_to_end(&mut decoded)
+            .map_err(|e| format!("Failed to decompress sitemap: {}", e))?;
+    } else {
+        decoded.extend_from_slice(bytes);
+    }
+    if decoded.len() as u64 > SITEMAP_MAX_BYTES {
+        return Err(format!("Sitemap is over the {} byte limit", SITEMAP_MAX_BYTES));
+    }
+    Ok(String::from_utf8_lossy(&decoded).to_string())
+}
+
+// Parse a sitemap index or urlset. Entries without a valid <loc> are skipped.
+pub(crate) fn parse_sitemap(xml: &str) -> Sitemap {
+    let is_index = xml.to_lowercase().contains("<sitemapindex");
+    let mut sitemaps = Vec::new();
+    let mut urls = Vec::new();
+    for entry in SITEMAP_ENTRY.captures_iter(xml) {
+        let block = &entry[1];
+        let loc = match tag_value(&SITEMAP_LOC, block).and_then(|loc| Url::parse(&loc).ok()) {
+            Some(loc) => loc,
+            None => continue,
+        };
+        if is_index {
+            sitemaps.push(loc);
+            continue;
+        }
+        let lastmod = tag_value(&SITEMAP_LASTMOD, block).filter(|lastmod| !lastmod.is_empty());
+        let priority = tag_value(&SITEMAP_PRIORITY, block)
+            .and_then(|priority| priority.parse::<f64>().ok())
+            .map(|priority| priority.clamp(0.0, 1.0));
+        urls.push(data::SitemapUrl { url: loc, lastmod, priority });
+    }
+    if is_index { Sitemap::Index(sitemaps) } else { Sitemap::UrlSet(urls) }
+}
+
+// Get the text of a tag, with CDATA and XML entities unwrapped.
+pub(crate) fn tag_value(re: &Regex, block: &str) -> Option<String> {
+    Some(xml_text(&re.captures(block)?[1]))
+}
+
+// Unwrap CDATA and XML entities from the text of a tag.
+pub(crate) fn xml_text(value: &str) -> String {
+    let value = value.trim();
+    let value = value.strip_prefix("<![CDATA[").and_then(|v| v.strip_suffix("]]>")).map(|v| v.trim()).unwrap_or(value);
+    value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+// Parse a <lastmod> in W3C datetime format, either a full timestamp or just the date.
+pub(crate) fn parse_lastmod(lastmod: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(lastmod) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(lastmod.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sitemap_urlset() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://www.example.com/?a=1&amp;b=2</loc><lastmod>2025-03-01</lastmod><priority>0.8</priority></url>
+              <url><loc><![CDATA[https://www.example.com/news]]></loc><priority>7</priority></url>
+              <url><loc>not a url</loc></url>
+            </urlset>"#;
+        let sitemap = parse_sitemap(xml);
+        assert_eq!(sitemap, Sitemap::UrlSet(vec![
+            data::SitemapUrl { url: Url::parse("https://www.example.com/?a=1&b=2").unwrap(), lastmod: Some("2025-03-01".to_string()), priority: Some(0.8) },
+            data::SitemapUrl { url: Url::parse("https://www.example.com/news").unwrap(), lastmod: None, priority: Some(1.0) },
+        ]));
+    }
+
+    #[test]
+    fn test_parse_sitemap_index() {
+        let xml = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://www.example.com/sitemap-1.xml.gz</loc><lastmod>2025-03-01T10:00:00+00:00</lastmod></sitemap>
+              <sitemap><loc>https://www.example.com/sitemap-2.xml</loc></sitemap>
+            </sitemapindex>"#;
+        let sitemap = parse_sitemap(xml);
+        assert_eq!(sitemap.kind(), "index");
+        assert_eq!(sitemap, Sitemap::Index(vec![
+            Url::parse("https://www.example.com/sitemap-1.xml.gz").unwrap(),
+            Url::parse("https://www.example.com/sitemap-2.xml").unwrap(),
+        ]));
+    }
+
+    #[test]
+    fn test_decode_gzip_xml() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        let xml = "<urlset><url><loc>https://www.example.com/</loc></url></urlset>";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(xml.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decode_xml(&compressed), Ok(xml.to_string()));
+        assert_eq!(decode_xml(xml.as_bytes()), Ok(xml.to_string()));
+        assert!(decode_xml(&[0x1f, 0x8b, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_parse_robots_sitemaps_and_lastmod() {
+        let robots = "User-agent: *\nDisallow: /private\nSitemap: https://www.example.com/sitemap.xml\nsitemap:https://cdn.example.com/news.xml # news\nSitemap: /relative.xml\n";
+        assert_eq!(parse_robots_sitemaps(robots), vec![
+            Url::parse("https://www.example.com/sitemap.xml").unwrap(),
+            Url::parse("https://cdn.example.com/news.xml").unwrap(),
+        ]);
+        assert_eq!(parse_lastmod("2025-03-01").unwrap().to_rfc3339(), "2025-03-01T00:00:00+00:00");
+        assert_eq!(parse_lastmod("2025-03-01T12:30:00+02:00").unwrap().to_rfc3339(), "2025-03-01T10:30:00+00:00");
+        assert_eq!(parse_lastmod("March"), None);
+    }
+}
@@ -1,22 +1,34 @@
 use rusqlite::{params, Connection, Result, ToSql};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use reqwest::Url;
+use crate::canonical;
 use crate::config;
 use crate::data;
+use crate::feeds;
 
 // Connect to the sqlite database, and run any migrations
 pub(crate) fn connect_sqlite_and_migrate(config: &config::Config) -> Result<Option<Connection>, Box<dyn Error>> {
     // Connect to sqlite
-    let results_db;
-    if !config.sqlite_enabled {
-        results_db = Connection::open_in_memory()?;
+    let results_db = if !config.sqlite_enabled {
+        Connection::open_in_memory()?
     } else  {
-        results_db = Connection::open(config.sqlite_path.clone())?;
-    }
+        Connection::open(config.sqlite_path.clone())?
+    };
 
     // Handle any migrations to setup the database
+    // Track which migrations have run, so the ones that alter tables are only applied once.
+    results_db.execute_batch("CREATE TABLE IF NOT EXISTS migrations (name TEXT PRIMARY KEY, applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP);")?;
     let migrations = get_sorted_migration_files()?;
-    for migration in migrations {
-        results_db.execute_batch(&migration)?;
+    for (name, migration) in migrations {
+        let applied: bool = results_db.query_row("SELECT EXISTS(SELECT 1 FROM migrations WHERE name = ?1)", [name], |row| row.get(0))?;
+        if applied {
+            continue;
+        }
+        let tx = results_db.unchecked_transaction()?;
+        tx.execute_batch(&migration)?;
+        tx.execute("INSERT INTO migrations (name) VALUES (?1)", [name])?;
+        tx.commit()?;
     }
     Ok(Some(results_db))
 }
@@ -24,29 +36,60 @@ pub(crate) fn connect_sqlite_and_migrate(config: &config::Config) -> Result<Opti
 pub(crate) fn insert_visited_site(conn: &Connection, visited_site: data::VisitedSite) -> Result<bool, Box<dyn Error>> {
     let visited_at = visited_site.visited_at().format("%Y-%m-%d %H:%M:%S").to_string();
     conn.execute("
-        INSERT INTO visited (url, referrer, last_visited_at, is_blocked) VALUES (?1, ?2, ?3, 0)
-        ON CONFLICT(url) DO UPDATE SET referrer = ?2, last_visited_at = strftime('%Y-%m-%d %H:%M:%S', 'now'), is_blocked = 0;
-        ", &[visited_site.url(), visited_site.referrer(), &visited_at])?;
+        INSERT INTO visited (url, referrer, last_visited_at, is_blocked, depth, domain) VALUES (?1, ?2, ?3, 0, ?4, ?5)
+        ON CONFLICT(url) DO UPDATE SET referrer = ?2, last_visited_at = strftime('%Y-%m-%d %H:%M:%S', 'now'), is_blocked = 0, depth = ?4, domain = ?5;
+        ", params![visited_site.url(), visited_site.referrer(), &visited_at, *visited_site.depth() as i64, visited_site.domain()])?;
     Ok(true)
 }
 
-pub(crate) fn insert_image(conn: &Connection, referrer: &String, url: &String, image: &Vec<u8>, name: &String, success: bool) -> Result<bool, Box<dyn Error>> {
+pub(crate) fn insert_image(conn: &Connection, referrer: &String, url: &String, image: &[u8], name: &String, success: bool) -> Result<bool, Box<dyn Error>> {
     let success_as_string = if success { "1" } else { "0" };
     conn.execute("
         INSERT INTO images (referrer, url, image, name, success) VALUES (?1, ?2, ?3, ?4, ?5)
-        ", params![&referrer, &url, image as &[u8], &name, success_as_string])?;
+        ", params![&referrer, &url, image, &name, success_as_string])?;
     Ok(true)
 }
 
 pub(crate) fn insert_html(conn: &Connection, url: &String, html: &String) -> Result<bool, Box<dyn Error>> {
     conn.execute("
         INSERT INTO html (url, html) VALUES (?1, ?2)
-        ", &[url, html])?;
+        ", [url, html])?;
+    Ok(true)
+}
+
+// Store a page's extracted text, replacing it if the page is crawled again.
+pub(crate) fn upsert_page_text(conn: &Connection, url: &String, page_text: &data::PageText) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO page_text (url, title, text, word_count) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(url) DO UPDATE SET title = excluded.title, text = excluded.text, word_count = excluded.word_count, updated_at = CURRENT_TIMESTAMP;
+        ", params![url, page_text.title, page_text.text, page_text.word_count as i64])?;
+    Ok(true)
+}
+
+pub(crate) fn insert_response(conn: &Connection, url: &String, response: &data::ResponseInfo) -> Result<bool, Box<dyn Error>> {
+    let headers: serde_json::Map<String, serde_json::Value> = response.headers.iter()
+        .map(|(name, value)| (name.clone(), serde_json::Value::String(value.clone())))
+        .collect();
+    conn.execute("
+        INSERT INTO responses (url, final_url, status_code, content_type, content_length, headers, ttfb_ms, duration_ms, error, attempt)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        ", params![
+            url,
+            response.final_url.as_ref().map(|url| url.to_string()),
+            response.status_code,
+            response.content_type,
+            response.content_length.map(|length| length as i64),
+            serde_json::Value::Object(headers).to_string(),
+            response.ttfb.as_millis() as i64,
+            response.duration.as_millis() as i64,
+            response.error,
+            response.attempt,
+        ])?;
     Ok(true)
 }
 
 pub(crate) fn mark_url_complete(conn: &Connection, url: &String) -> Result<bool, Box<dyn Error>> {
-    conn.execute("UPDATE visited SET is_complete = 1 WHERE url = ?1", &[url])?;
+    conn.execute("UPDATE visited SET is_complete = 1 WHERE url = ?1", [url])?;
     Ok(true)
 }
 
@@ -54,14 +97,43 @@ pub(crate) fn mark_url_blocked(conn: &Connection, url: &String, referrer: &Strin
     conn.execute("
         INSERT INTO visited (url, referrer, last_visited_at, is_blocked) VALUES (?1, ?2, strftime('%Y-%m-%d %H:%M:%S', 'now'), 1)
         ON CONFLICT(url) DO UPDATE SET referrer = ?2, last_visited_at = strftime('%Y-%m-%d %H:%M:%S', 'now'), is_blocked = 1;
-        ", &[url, referrer])?;
+        ", [url, referrer])?;
+    Ok(true)
+}
+
+// Log a URL the crawler decided not to follow, and why.
+pub(crate) fn insert_rejected_url(conn: &Connection, url: &String, referrer: &String, reason: &str, detail: &String) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO rejected_urls (url, referrer, reason, detail) VALUES (?1, ?2, ?3, ?4)
+        ", params![url, referrer, reason, detail])?;
+    Ok(true)
+}
+
+// Record the rel=canonical URL a page declared.
+pub(crate) fn set_canonical_url(conn: &Connection, url: &String, canonical_url: &String) -> Result<bool, Box<dyn Error>> {
+    conn.execute("UPDATE visited SET canonical_url = ?2 WHERE url = ?1", [url, canonical_url])?;
+    Ok(true)
+}
+
+// Record the noindex and nofollow directives a page gave us.
+pub(crate) fn set_robots_directives(conn: &Connection, url: &String, directives: &str) -> Result<bool, Box<dyn Error>> {
+    conn.execute("UPDATE visited SET robots_directives = ?2 WHERE url = ?1", params![url, directives])?;
     Ok(true)
 }
 
+// Check if another page already captured this canonical URL, either by declaring it or by being it.
+pub(crate) fn is_canonical_captured(conn: &Connection, canonical_url: &String, url: &String) -> Result<bool, Box<dyn Error>> {
+    let mut stmt = conn.prepare("
+        SELECT 1 FROM visited WHERE url != ?2 AND (canonical_url = ?1 OR (url = ?1 AND is_complete = 1)) LIMIT 1
+        ")?;
+    let mut rows = stmt.query([canonical_url, url])?;
+    Ok(rows.next()?.is_some())
+}
+
 #[allow(dead_code)] // Not in use right now.
 pub(crate) fn is_previously_visited_url(conn: &Connection, url: &String) -> Result<Option<bool>, Box<dyn Error>> {
     let mut stmt = conn.prepare("SELECT 1 FROM visited WHERE url = ?1 LIMIT 1")?;
-    let mut rows = stmt.query(&[url])?;
+    let mut rows = stmt.query([url])?;
     let row = rows.next()?;
     match row {
         Some(_) => Ok(Some(true)),
@@ -71,7 +143,7 @@ pub(crate) fn is_previously_visited_url(conn: &Connection, url: &String) -> Resu
 
 pub(crate) fn is_previously_completed_url(conn: &Connection, url: &String) -> Result<Option<bool>, Box<dyn Error>> {
     let mut stmt = conn.prepare("SELECT 1 FROM visited WHERE url = ?1 AND is_complete = 1 LIMIT 1")?;
-    let mut rows = stmt.query(&[url])?;
+    let mut rows = stmt.query([url])?;
     let row = rows.next()?;
     match row {
         Some(_) => Ok(Some(true)),
@@ -79,13 +151,168 @@ pub(crate) fn is_previously_completed_url(conn: &Connection, url: &String) -> Re
     }
 }
 
+// Add a discovered URL to the frontier, if it isn't already there.
+pub(crate) fn insert_frontier_url(conn: &Connection, url: &Url, referrer: &String, depth: usize, seed: Option<&Url>) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO frontier (url, referrer, depth, state, seed) VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT(url) DO NOTHING;
+        ", params![url.as_str(), referrer, depth as i64, data::FrontierState::Queued.as_str(), seed.map(|seed| seed.as_str())])?;
+    Ok(true)
+}
+
+// Record the lastmod and priority a sitemap gave a frontier URL.
+pub(crate) fn set_frontier_sitemap(conn: &Connection, url: &Url, lastmod: Option<&str>, priority: Option<f64>) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        UPDATE frontier SET lastmod = ?2, sitemap_priority = ?3, updated_at = strftime('%Y-%m-%d %H:%M:%S', 'now') WHERE url = ?1
+        ", params![url.as_str(), lastmod, priority])?;
+    Ok(true)
+}
+
+// Record a sitemap we fetched, and how many entries it had.
+pub(crate) fn insert_sitemap(conn: &Connection, url: &Url, kind: &str, url_count: usize) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO sitemaps (url, host, kind, url_count) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(url) DO UPDATE SET kind = excluded.kind, url_count = excluded.url_count, fetched_at = CURRENT_TIMESTAMP;
+        ", params![url.as_str(), url.host_str(), kind, url_count as i64])?;
+    Ok(true)
+}
+
+// Record a feed we fetched, and the page we found it on.
+pub(crate) fn insert_feed(conn: &Connection, url: &Url, found_on: &String, feed: &feeds::Feed) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO feeds (url, host, kind, title, item_count, found_on) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(url) DO UPDATE SET kind = excluded.kind, title = excluded.title, item_count = excluded.item_count, fetched_at = CURRENT_TIMESTAMP;
+        ", params![url.as_str(), url.host_str(), feed.kind, feed.title, feed.items.len() as i64, found_on])?;
+    Ok(true)
+}
+
+// Record an item listed in a feed.
+pub(crate) fn insert_feed_item(conn: &Connection, feed_url: &Url, url: &String, item: &feeds::FeedItem) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO feed_items (feed_url, url, title, published) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(feed_url, url) DO UPDATE SET title = excluded.title, published = excluded.published, fetched_at = CURRENT_TIMESTAMP;
+        ", params![feed_url.as_str(), url, item.title, item.published])?;
+    Ok(true)
+}
+
+// Store the robots.txt we fetched for an origin, replacing any older copy.
+pub(crate) fn upsert_robots_txt(conn: &Connection, origin: &str, status_code: Option<u16>, body: &str) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        INSERT INTO robots_txt (origin, status_code, body) VALUES (?1, ?2, ?3)
+        ON CONFLICT(origin) DO UPDATE SET status_code = excluded.status_code, body = excluded.body, fetched_at = CURRENT_TIMESTAMP;
+        ", params![origin, status_code, body])?;
+    Ok(true)
+}
+
+// Get the stored robots.txt for an origin, with its age in seconds.
+pub(crate) fn get_robots_txt(conn: &Connection, origin: &str) -> Result<Option<data::StoredRobotsTxt>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("SELECT status_code, body, CAST(strftime('%s', 'now') - strftime('%s', fetched_at) AS INTEGER) FROM robots_txt WHERE origin = ?1")?;
+    let mut rows = stmt.query([origin])?;
+    match rows.next()? {
+        Some(row) => {
+            let status_code: Option<u16> = row.get(0)?;
+            let body: String = row.get(1)?;
+            let age: i64 = row.get(2)?;
+            Ok(Some(data::StoredRobotsTxt { status_code, body, age_secs: age.max(0) as u64 }))
+        },
+        None => Ok(None)
+    }
+}
+
+pub(crate) fn set_frontier_state(conn: &Connection, url: &Url, state: data::FrontierState) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        UPDATE frontier SET state = ?2, updated_at = strftime('%Y-%m-%d %H:%M:%S', 'now') WHERE url = ?1
+        ", params![url.as_str(), state.as_str()])?;
+    Ok(true)
+}
+
+// Record how many times we've tried to fetch a URL, and why the last attempt failed.
+pub(crate) fn set_frontier_attempts(conn: &Connection, url: &Url, attempts: u32, last_error: Option<&str>) -> Result<bool, Box<dyn Error>> {
+    conn.execute("
+        UPDATE frontier SET attempts = ?2, last_error = COALESCE(?3, last_error), updated_at = strftime('%Y-%m-%d %H:%M:%S', 'now') WHERE url = ?1
+        ", params![url.as_str(), attempts, last_error])?;
+    Ok(true)
+}
+
+// Get every URL that was queued or in-flight when the last run stopped.
+// Anything left in the fetching state was interrupted, so it goes back in the queue.
+pub(crate) fn get_pending_frontier(conn: &Connection) -> Result<Vec<data::FrontierEntry>, Box<dyn Error>> {
+    conn.execute("UPDATE frontier SET state = ?1 WHERE state = ?2",
+        params![data::FrontierState::Queued.as_str(), data::FrontierState::Fetching.as_str()])?;
+    let mut stmt = conn.prepare("SELECT url, referrer, depth, seed, lastmod, sitemap_priority FROM frontier WHERE state = ?1 ORDER BY id")?;
+    let rows = stmt.query_map([data::FrontierState::Queued.as_str()], |row| {
+        let url: String = row.get(0)?;
+        let referrer: String = row.get(1)?;
+        let depth: i64 = row.get(2)?;
+        let seed: Option<String> = row.get(3)?;
+        let lastmod: Option<String> = row.get(4)?;
+        let sitemap_priority: Option<f64> = row.get(5)?;
+        Ok((url, referrer, depth, seed, lastmod, sitemap_priority))
+    })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        let (url, referrer, depth, seed, lastmod, sitemap_priority) = row?;
+        let seed = seed.and_then(|seed| Url::parse(&seed).ok());
+        match Url::parse(&url) {
+            Ok(url) => entries.push(data::FrontierEntry::new(url, referrer, depth as usize, seed).with_sitemap(lastmod, sitemap_priority)),
+            Err(_) => continue,
+        }
+    }
+    Ok(entries)
+}
+
+// Get every URL the crawler has already stored or queued.
+pub(crate) fn get_seen_urls(conn: &Connection, config: &config::Config) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut seen = Vec::new();
+    let mut stmt = conn.prepare("SELECT url FROM visited")?;
+    for url in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        seen.push(url?);
+    }
+    let mut stmt = conn.prepare("SELECT url FROM frontier")?;
+    for url in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        seen.push(canonical::canonicalize(config, &url?));
+    }
+    Ok(seen)
+}
+
+// Count the pages we've visited or still have queued on each domain, so the page budgets survive a resume.
+// A queued URL that already has a visited row, such as one whose fetch was interrupted, is only counted once.
+pub(crate) fn get_domain_page_counts(conn: &Connection, config: &config::Config) -> Result<Vec<(String, usize)>, Box<dyn Error>> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut stmt = conn.prepare("SELECT url, domain FROM visited WHERE is_blocked = 0")?;
+    let rows = stmt.query_map([], |row| {
+        let url: String = row.get(0)?;
+        let domain: Option<String> = row.get(1)?;
+        Ok((url, domain))
+    })?;
+    for row in rows {
+        let (url, domain) = row?;
+        if let Some(domain) = domain {
+            *counts.entry(domain).or_insert(0) += 1;
+        }
+        visited.insert(url);
+    }
+
+    let mut stmt = conn.prepare("SELECT url FROM frontier WHERE state = ?1")?;
+    for url in stmt.query_map([data::FrontierState::Queued.as_str()], |row| row.get::<_, String>(0))? {
+        let url = url?;
+        if visited.contains(&canonical::canonicalize(config, &url)) {
+            continue;
+        }
+        if let Some(domain) = Url::parse(&url).ok().and_then(|url| url.host_str().map(|host| host.to_string())) {
+            *counts.entry(domain).or_insert(0) += 1;
+        }
+    }
+    Ok(counts.into_iter().collect())
+}
+
 pub(crate) fn connect_and_get_total_rows(config: &config::Config) -> Result<u64, Box<dyn Error>> {
-    let results_db;
     if !config.sqlite_enabled {
         return Ok(0);
-    } else  {
-        results_db = Connection::open(config.sqlite_path.clone())?;
     }
+    let results_db = Connection::open(config.sqlite_path.clone())?;
     let mut stmt = results_db.prepare("SELECT COUNT(*) FROM visited")?;
     let mut rows = stmt.query(&[] as &[&dyn ToSql])?;
 
@@ -99,12 +326,10 @@ pub(crate) fn connect_and_get_total_rows(config: &config::Config) -> Result<u64,
 }
 
 pub(crate) fn connect_and_get_completed_rows(config: &config::Config) -> Result<u64, Box<dyn Error>> {
-    let results_db;
     if !config.sqlite_enabled {
         return Ok(0);
-    } else  {
-        results_db = Connection::open(config.sqlite_path.clone())?;
     }
+    let results_db = Connection::open(config.sqlite_path.clone())?;
     let mut stmt = results_db.prepare("SELECT COUNT(*) FROM visited WHERE is_complete = 1")?;
     let mut rows = stmt.query(&[] as &[&dyn ToSql])?;
 
@@ -117,10 +342,98 @@ pub(crate) fn connect_and_get_completed_rows(config: &config::Config) -> Result<
     }
 }
 
+pub(crate) fn connect_and_get_pending_frontier_rows(config: &config::Config) -> Result<u64, Box<dyn Error>> {
+    if !config.sqlite_enabled {
+        return Ok(0);
+    }
+    let results_db = Connection::open(config.sqlite_path.clone())?;
+    let count: i64 = results_db.query_row("SELECT COUNT(*) FROM frontier WHERE state IN (?1, ?2)",
+        params![data::FrontierState::Queued.as_str(), data::FrontierState::Fetching.as_str()], |row| row.get(0))?;
+    Ok(count as u64)
+}
+
+// Count the frontier rows reached from each seed, by state.
+pub(crate) fn connect_and_get_seed_stats(config: &config::Config) -> Result<Vec<data::SeedStats>, Box<dyn Error>> {
+    if !config.sqlite_enabled {
+        return Ok(Vec::new());
+    }
+    let results_db = Connection::open(config.sqlite_path.clone())?;
+    let mut stmt = results_db.prepare("SELECT seed, state, COUNT(*) FROM frontier WHERE seed IS NOT NULL GROUP BY seed, state ORDER BY MIN(id)")?;
+    let rows = stmt.query_map([], |row| {
+        let seed: String = row.get(0)?;
+        let state: String = row.get(1)?;
+        let count: i64 = row.get(2)?;
+        Ok((seed, state, count as u64))
+    })?;
+
+    let mut stats: Vec<data::SeedStats> = Vec::new();
+    for row in rows {
+        let (seed, state, count) = row?;
+        let index = match stats.iter().position(|s| s.seed == seed) {
+            Some(index) => index,
+            None => {
+                stats.push(data::SeedStats { seed, ..Default::default() });
+                stats.len() - 1
+            }
+        };
+        let seed_stats = &mut stats[index];
+        seed_stats.discovered += count;
+        if state == data::FrontierState::Fetched.as_str() {
+            seed_stats.fetched += count;
+        } else if state == data::FrontierState::Failed.as_str() {
+            seed_stats.failed += count;
+        } else {
+            seed_stats.pending += count;
+        }
+    }
+    Ok(stats)
+}
+
 // Get the sql migrations we've set. Include them in the binary, the user doesn't need to see them.
-fn get_sorted_migration_files() -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let migrations: Vec<String> = vec![
-        include_str!("../db/migrations/012425_init.sql").to_string(),
+fn get_sorted_migration_files() -> Result<Vec<(&'static str, String)>, Box<dyn std::error::Error>> {
+    let migrations: Vec<(&'static str, String)> = vec![
+        ("012425_init", include_str!("../db/migrations/012425_init.sql").to_string()),
+        ("021025_frontier", include_str!("../db/migrations/021025_frontier.sql").to_string()),
+        ("021525_depth_budgets", include_str!("../db/migrations/021525_depth_budgets.sql").to_string()),
+        ("030125_responses", include_str!("../db/migrations/030125_responses.sql").to_string()),
+        ("030525_retries", include_str!("../db/migrations/030525_retries.sql").to_string()),
+        ("031025_canonical", include_str!("../db/migrations/031025_canonical.sql").to_string()),
+        ("031525_rejected_urls", include_str!("../db/migrations/031525_rejected_urls.sql").to_string()),
+        ("032025_seeds", include_str!("../db/migrations/032025_seeds.sql").to_string()),
+        ("032525_sitemaps", include_str!("../db/migrations/032525_sitemaps.sql").to_string()),
+        ("033025_feeds", include_str!("../db/migrations/033025_feeds.sql").to_string()),
+        ("040125_robots_txt", include_str!("../db/migrations/040125_robots_txt.sql").to_string()),
+        ("040525_robots_directives", include_str!("../db/migrations/040525_robots_directives.sql").to_string()),
+        ("041025_page_text", include_str!("../db/migrations/041025_page_text.sql").to_string()),
     ];
     Ok(migrations)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    #[test]
+    fn test_resume_after_interrupted_fetch() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.sqlite_enabled = false;
+        let conn = connect_sqlite_and_migrate(&config).unwrap().unwrap();
+
+        // One page was fetched, one was being fetched when the crawl stopped, and one is still queued.
+        let referrer = "https://www.example.com/".to_string();
+        for (path, state) in [("/a", data::FrontierState::Fetched), ("/b", data::FrontierState::Fetching), ("/c", data::FrontierState::Queued)] {
+            let url = Url::parse(&format!("https://www.example.com{}", path)).unwrap();
+            insert_frontier_url(&conn, &url, &referrer, 1, None).unwrap();
+            set_frontier_state(&conn, &url, state).unwrap();
+            if state != data::FrontierState::Queued {
+                let visited_site = data::VisitedSite::new(canonical::canonicalize(&config, url.as_str()), referrer.clone(), Local::now(), 1, Some("www.example.com".to_string()));
+                insert_visited_site(&conn, visited_site).unwrap();
+            }
+        }
+
+        let pending: Vec<String> = get_pending_frontier(&conn).unwrap().iter().map(|entry| entry.url.to_string()).collect();
+        assert_eq!(pending, vec!["https://www.example.com/b", "https://www.example.com/c"]);
+        assert_eq!(get_domain_page_counts(&conn, &config).unwrap(), vec![("www.example.com".to_string(), 3)]);
+    }
+}
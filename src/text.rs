@@ -0,0 +1,195 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::data;
+
+// Elements that never hold a page's main content.
+const BOILERPLATE_TAGS: [&str; 16] = ["script", "style", "noscript", "template", "nav", "footer", "aside", "form", "iframe", "svg", "canvas", "button", "select", "textarea", "dialog", "head"];
+// ARIA roles for navigation, site headers and footers, and sidebars.
+const BOILERPLATE_ROLES: [&str; 6] = ["navigation", "banner", "contentinfo", "complementary", "search", "dialog"];
+// Words in a class or id that mark ads and page furniture. They're matched against whole words, so "ad" doesn't match "header".
+const BOILERPLATE_NAMES: [&str; 24] = ["ad", "ads", "adsbygoogle", "advert", "advertisement", "sponsor", "sponsored", "promo", "banner", "cookie", "cookies", "consent", "newsletter", "subscribe", "social", "share", "sharing", "related", "comments", "sidebar", "breadcrumb", "breadcrumbs", "menu", "popup"];
+// Elements that start a new block of text.
+const BLOCK_TAGS: [&str; 26] = ["p", "div", "section", "article", "main", "header", "blockquote", "ul", "ol", "dl", "dt", "dd", "table", "tr", "td", "th", "figure", "figcaption", "hr", "address", "details", "summary", "body", "caption", "center", "hgroup"];
+
+// Collects a page's text as a list of blocks, so headings, paragraphs and list items stay on their own lines.
+struct TextWriter {
+    blocks: Vec<String>,
+    current: String,
+    prefix: String,
+}
+
+impl TextWriter {
+    fn new() -> Self {
+        TextWriter { blocks: Vec::new(), current: String::new(), prefix: String::new() }
+    }
+
+    // End the current block, collapsing its whitespace but keeping any <br> line breaks.
+    fn flush(&mut self) {
+        let lines: Vec<String> = self.current.split('\n')
+            .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect();
+        if !lines.is_empty() {
+            self.blocks.push(format!("{}{}", self.prefix, lines.join("\n")));
+        }
+        self.current.clear();
+        self.prefix.clear();
+    }
+
+    // Start a new block, such as "## " for a heading or "- " for a list item.
+    fn start_block(&mut self, prefix: &str) {
+        self.flush();
+        self.prefix = prefix.to_string();
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        self.blocks.join("\n\n")
+    }
+}
+
+// Extract the main content of a page as plain text, without its navigation, footer, scripts and ads.
+// Headings are kept as "#" lines and list items as "- " lines, with a blank line between blocks.
+pub(crate) fn extract_page_text(doc: &Html) -> data::PageText {
+    let title_selector = Selector::parse("title").unwrap();
+    let title = doc.select(&title_selector).next()
+        .map(|title| title.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|title| !title.is_empty());
+
+    let mut writer = TextWriter::new();
+    write_element(find_main_content(doc), &mut writer);
+    let text = writer.finish();
+    let word_count = count_words(&text);
+    data::PageText { title, text, word_count }
+}
+
+// Pick the element holding the page's main content: its <main>, the <article> with the most text, or failing that the whole page.
+fn find_main_content(doc: &Html) -> ElementRef<'_> {
+    let main_selector = Selector::parse("main, [role=main]").unwrap();
+    if let Some(main) = doc.select(&main_selector).find(|main| !is_boilerplate(main)) {
+        return main;
+    }
+    let article_selector = Selector::parse("article").unwrap();
+    let article = doc.select(&article_selector)
+        .filter(|article| !is_boilerplate(article))
+        .max_by_key(|article| article.text().map(|text| text.trim().len()).sum::<usize>());
+    article.unwrap_or_else(|| doc.root_element())
+}
+
+fn write_element(element: ElementRef<'_>, writer: &mut TextWriter) {
+    if is_boilerplate(&element) {
+        return;
+    }
+    let name = element.value().name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            writer.start_block(&format!("{} ", "#".repeat(level)));
+            write_children(element, writer);
+            writer.flush();
+        }
+        "li" => {
+            writer.start_block("- ");
+            write_children(element, writer);
+            writer.flush();
+        }
+        // Preformatted text keeps its own whitespace.
+        "pre" => {
+            writer.flush();
+            let text = element.text().collect::<String>();
+            let text = text.trim_matches('\n').trim_end();
+            if !text.is_empty() {
+                writer.blocks.push(text.to_string());
+            }
+        }
+        "br" => writer.current.push('\n'),
+        _ if BLOCK_TAGS.contains(&name) => {
+            writer.flush();
+            write_children(element, writer);
+            writer.flush();
+        }
+        _ => write_children(element, writer),
+    }
+}
+
+fn write_children(element: ElementRef<'_>, writer: &mut TextWriter) {
+    for child in element.children() {
+        match ElementRef::wrap(child) {
+            Some(child) => write_element(child, writer),
+            None => {
+                // Line breaks in the source are just whitespace, only <br> starts a new line.
+                if let Node::Text(text) = child.value() {
+                    writer.current.extend(text.chars().map(|c| if c.is_whitespace() { ' ' } else { c }));
+                }
+            }
+        }
+    }
+}
+
+// Check whether an element is navigation, a footer, a script, an ad or anything else that isn't content.
+fn is_boilerplate(element_ref: &ElementRef) -> bool {
+    let element = element_ref.value();
+    let name = element.name();
+    if BOILERPLATE_TAGS.contains(&name) {
+        return true;
+    }
+    if element.attr("hidden").is_some() || element.attr("aria-hidden").is_some_and(|hidden| hidden.eq_ignore_ascii_case("true")) {
+        return true;
+    }
+    if element.attr("role").is_some_and(|role| BOILERPLATE_ROLES.iter().any(|boilerplate| role.eq_ignore_ascii_case(boilerplate))) {
+        return true;
+    }
+    // <main> and <article> name their own content, so a class like "article-related" doesn't drop the whole page.
+    if name == "main" || name == "article" || name == "body" {
+        return false;
+    }
+    // A <header> outside the main content is the site's banner, rather than an article's heading.
+    if name == "header" && !element_ref.ancestors().filter_map(ElementRef::wrap).any(|ancestor| matches!(ancestor.value().name(), "main" | "article")) {
+        return true;
+    }
+    let names = element.attr("class").unwrap_or("").to_string() + " " + element.attr("id").unwrap_or("");
+    names.split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| BOILERPLATE_NAMES.iter().any(|boilerplate| word.eq_ignore_ascii_case(boilerplate)))
+}
+
+// Count the words in extracted text, leaving out the "#" and "-" markers.
+pub(crate) fn count_words(text: &str) -> usize {
+    text.split_whitespace().filter(|word| word.chars().any(|c| c.is_alphanumeric())).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools;
+
+    #[test]
+    fn test_extract_page_text() {
+        let doc = tools::parse_html(include_str!("../tests/fixtures/article.html")).unwrap();
+        let page_text = extract_page_text(&doc);
+        assert_eq!(page_text.title.as_deref(), Some("How Crabs Walk | Example News"));
+        assert_eq!(page_text.text, "# How crabs walk\n\n\
+            Crabs walk sideways because of the way their legs bend.\n\n\
+            ## Why sideways\n\n\
+            Their joints only bend outwards.\nMost species can still walk forwards slowly.\n\n\
+            - Ghost crabs are the fastest.\n\n\
+            - Hermit crabs walk forwards.\n\n\
+            let legs = 10;\n    walk(legs);");
+        assert_eq!(page_text.word_count, 40);
+    }
+
+    #[test]
+    fn test_extract_page_text_without_main() {
+        let html = "<html><body><header class='site-header'>Site</header><nav>Home</nav><div id='ad-slot'>Buy now</div>\
+            <div class='content'><p>Just a <b>plain</b> page.</p></div><footer>Footer</footer></body></html>";
+        let page_text = extract_page_text(&tools::parse_html(html).unwrap());
+        assert_eq!(page_text.title, None);
+        assert_eq!(page_text.text, "Just a plain page.");
+        assert_eq!(page_text.word_count, 4);
+    }
+
+    #[test]
+    fn test_count_words() {
+        assert_eq!(count_words("# A heading\n\n- one item, two\n\n-- 3"), 6);
+        assert_eq!(count_words(""), 0);
+    }
+}
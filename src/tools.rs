@@ -1,16 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::panic;
 use std::env;
+use std::io::Read;
 use std::sync::{Arc, Mutex};
-use lazy_static::lazy_static;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use rayon::ThreadPool;
+use std::time::Duration;
+use futures::future::join_all;
 use regex::Regex;
-use reqwest::Url;
+use reqwest::{Client, Url};
 use robotstxt::DefaultMatcher;
 use rusqlite::Connection;
 use scraper::{Html, Selector};
-use crate::{config, data, http, sqlite};
+use tokio::sync::Semaphore;
+use crate::{canonical, config, data, domains, frontier, http, politeness, robots, rules, sqlite, traps};
 
 pub(crate) fn debug_log(debug: bool, log_message: &str) {
     if debug {
@@ -18,27 +19,17 @@ pub(crate) fn debug_log(debug: bool, log_message: &str) {
     }
 }
 
-pub(crate) fn is_robots_txt_blocked(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, url: Url, referrer_url: &String) -> bool {
-    // We have to cache this or its death for performance.
-    let domain = url.domain().unwrap();
-    let robots_txt = INMEMORY_CACHE.get(domain).unwrap_or_else(|| {
-        let curr_robots_txt = match reqwest::blocking::get(format!("https://{}/robots.txt", domain)) {
-            Ok(response) => response.text().unwrap(),
-            Err(_) => match reqwest::blocking::get(format!("http://{}/robots.txt", domain)) {
-                Ok(response) => match response.text() {
-                    Ok(text) => text,
-                    Err(_) => return "".to_string(),
-                },
-                Err(_) => return "".to_string(),
-            },
-        };
-        INMEMORY_CACHE.set(domain.to_string(), curr_robots_txt.clone());
-        curr_robots_txt
-    });
+// Check a URL against its host's robots.txt rules, and mark it as blocked in SQLite if it's disallowed.
+pub(crate) fn is_robots_txt_blocked(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, robots_txt: &robots::RobotsTxt, url: Url, referrer_url: &str) -> bool {
+    let rules = match robots_txt {
+        robots::RobotsTxt::Rules(rules) => rules,
+        robots::RobotsTxt::AllowAll => return false,
+        robots::RobotsTxt::Unreachable => return true,
+    };
 
     // This can panic if the robots.txt is invalid
     let result = panic::catch_unwind(|| {
-        !DefaultMatcher::default().allowed_by_robots(&robots_txt, config.user_agents.iter().map(|s| s.as_str()).collect(), url.as_str())
+        !DefaultMatcher::default().one_agent_allowed_by_robots(rules, &config.robots_user_agent, url.as_str())
     });
     let blocked = match result {
         Ok(blocked) => blocked,
@@ -48,38 +39,85 @@ pub(crate) fn is_robots_txt_blocked(config: &config::Config, db_conn: &Arc<Mutex
         }
     };
     if blocked {
-        let formatted_link_url = format_url_for_storage(url.to_string());
-        let formatted_referrer_url = format_url_for_storage(referrer_url.to_string());
-        if let Err(e) = sqlite::mark_url_blocked(&mut db_conn.lock().unwrap(), &formatted_link_url, &formatted_referrer_url) {
+        let formatted_link_url = canonical::canonicalize(config, url.as_str());
+        let formatted_referrer_url = canonical::canonicalize(config, referrer_url);
+        if let Err(e) = sqlite::mark_url_blocked(&db_conn.lock().unwrap(), &formatted_link_url, &formatted_referrer_url) {
             debug_log(config.debug, &format!("Failed to mark URL {} as blocked in SQLite: {}", url, e));
         }
     }
     blocked
 }
 
+// Find the Crawl-delay in a robots.txt, preferring a group that names our robots user agent over the * group.
+pub(crate) fn parse_crawl_delay(robots_txt: &str, robots_user_agent: &str) -> Option<Duration> {
+    let mut group_agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+    let mut specific_delay = None;
+    let mut wildcard_delay = None;
+    for line in robots_txt.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        if key == "user-agent" {
+            // A user-agent line after any rules starts a new group.
+            if in_rules {
+                group_agents.clear();
+                in_rules = false;
+            }
+            group_agents.push(value.to_lowercase());
+            continue;
+        }
+        in_rules = true;
+        if key != "crawl-delay" {
+            continue;
+        }
+        let delay = match value.parse::<f64>() {
+            Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Duration::from_secs_f64(seconds),
+            _ => continue,
+        };
+        if group_agents.iter().any(|agent| is_robots_user_agent(agent, robots_user_agent)) {
+            specific_delay.get_or_insert(delay);
+        } else if group_agents.iter().any(|agent| agent == "*") {
+            wildcard_delay.get_or_insert(delay);
+        }
+    }
+    specific_delay.or(wildcard_delay)
+}
+
+// Check whether a robots.txt User-agent line names our product token.
+// Like the matcher, only the leading letters, - and _ of the line count, and case is ignored.
+pub(crate) fn is_robots_user_agent(group_agent: &str, robots_user_agent: &str) -> bool {
+    let token: String = group_agent.chars().take_while(|c| c.is_ascii_alphabetic() || *c == '-' || *c == '_').collect();
+    !token.is_empty() && token.eq_ignore_ascii_case(robots_user_agent)
+}
+
 // Save image data, and the links to the database
-pub(crate) fn save_image_links(config: &config::Config, pool: &Arc<ThreadPool>, site_urls: &data::SiteUrls, db_conn: &Arc<Mutex<Connection>>, target_url: &Url) {
-    pool.install(|| {
-        site_urls.img_urls.clone().into_par_iter().for_each(|url| {
-            let result = http::fetch_image(config, &url);
-            let image_data = match &result {
-                Ok(content) => content,
-                Err(e) => {
-                    debug_log(config.debug, &format!("Failed to fetch image from {}: {}", url, e));
-                    return
-                }
-            };
-            // Check if we got any image data
-            if image_data.len() == 0 {
+pub(crate) async fn save_image_links(config: &config::Config, client: &Client, requests: &Semaphore, site_urls: &data::SiteUrls, db_conn: &Arc<Mutex<Connection>>, target_url: &Url, politeness: &politeness::PolitenessScheduler) {
+    join_all(site_urls.img_urls.iter().map(|url| async move {
+        politeness.wait_for_turn(config, client, db_conn, url).await;
+        let result = {
+            let _permit = requests.acquire().await;
+            http::fetch_image(config, client, db_conn, url).await
+        };
+        let image_data = match &result {
+            Ok(content) => content,
+            Err(e) => {
+                debug_log(config.debug, &format!("Failed to fetch image from {}: {}", url, e));
                 return
             }
+        };
+        // Check if we got any image data
+        if image_data.is_empty() {
+            return
+        }
 
-            // Expect an image name at the end of the url, grab it
-            let name = url.path_segments().and_then(|segments| segments.last()).unwrap_or(".jpg");
-            let _ = sqlite::insert_image(&mut db_conn.lock().unwrap(), &format_url_for_storage(target_url.to_string()),&format_url_for_storage(url.to_string()), image_data, &name.to_string(), result.is_ok())
-                .map_err(|e| debug_log(config.debug, &format!("Failed to insert image into SQLite: {}", e)));
-        });
-    });
+        // Expect an image name at the end of the url, grab it
+        let name = url.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or(".jpg");
+        let _ = sqlite::insert_image(&db_conn.lock().unwrap(), &canonical::canonicalize(config, target_url.as_str()), &canonical::canonicalize(config, url.as_str()), image_data, &name.to_string(), result.is_ok())
+            .map_err(|e| debug_log(config.debug, &format!("Failed to insert image into SQLite: {}", e)));
+    })).await;
 }
 
 // Parse HTML content into a scraper::Html object
@@ -104,59 +142,155 @@ fn extract_attributes(doc: &Html, selector_str: &str, attr: &str) -> Vec<String>
 pub(crate) fn extract_links(doc: &Html) -> Result<data::SiteLinks, Box<dyn std::error::Error>> {
     let link_links = extract_attributes(doc, "a[href]", "href");
     let img_links = extract_attributes(doc, "img[src]", "src");
+    // Only the first <base> with an href counts.
+    let base_href = extract_attributes(doc, "base[href]", "href").into_iter().next();
+    let canonical_href = extract_attributes(doc, "link[rel~=canonical][href]", "href").into_iter().next();
+    // Feeds are advertised with <link rel="alternate">, or just linked to at a URL that looks like a feed.
+    let mut feed_links = extract_attributes(doc, "link[rel~=alternate][type='application/rss+xml' i][href], link[rel~=alternate][type='application/atom+xml' i][href]", "href");
+    feed_links.extend(extract_attributes(doc, "a[href$='.rss' i], a[href$='.atom' i], a[href$='/feed'], a[href$='/feed/'], a[href$='/rss'], a[href$='/rss/']", "href"));
+    let mut unique = HashSet::new();
+    feed_links.retain(|link| unique.insert(link.clone()));
+    // A link counts as nofollow only if it isn't also linked to normally somewhere on the page.
+    let followed: HashSet<String> = extract_attributes(doc, "a[href]:not([rel~=nofollow i]):not([rel~=ugc i]):not([rel~=sponsored i])", "href").into_iter().collect();
+    let mut nofollow_links = extract_attributes(doc, "a[href][rel~=nofollow i], a[href][rel~=ugc i], a[href][rel~=sponsored i]", "href");
+    nofollow_links.retain(|link| !followed.contains(link));
+    let meta_selector = Selector::parse("meta[name][content]").unwrap();
+    let meta_tags = doc.select(&meta_selector)
+        .filter_map(|element| Some((element.value().attr("name")?.trim().to_string(), element.value().attr("content")?.to_string())))
+        .collect();
 
     Ok(data::SiteLinks {
         link_links,
         img_links,
+        base_href,
+        canonical_href,
+        feed_links,
+        nofollow_links,
+        meta_tags,
     })
 }
 
+// Work out the URL that relative links on a page are resolved against.
+// That's the page's <base href>, itself resolved against the page URL, or the page URL if there isn't a usable one.
+pub(crate) fn resolve_base_url(base_href: Option<&str>, page_url: &Url) -> Url {
+    base_href
+        .and_then(|href| page_url.join(href.trim()).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .unwrap_or_else(|| page_url.clone())
+}
+
+// The checks a new link has to pass, on top of the domain lists, before it's followed.
+pub(crate) struct LinkFilters {
+    pub(crate) domains: domains::DomainRules,
+    pub(crate) rules: rules::UrlRules,
+    pub(crate) traps: traps::TrapDetector,
+}
+impl LinkFilters {
+    pub fn new(config: &config::Config, seeds: &[Url]) -> Self {
+        LinkFilters {
+            domains: domains::DomainRules::new(config, seeds),
+            rules: rules::UrlRules::new(config),
+            traps: traps::TrapDetector::new(config),
+        }
+    }
+}
+
+// Log a link we decided not to follow to the rejected_urls table.
+fn log_rejected_url(config: &config::Config, db_conn: &Arc<Mutex<Connection>>, url: &str, referrer_url: &str, reason: &str, detail: &String) {
+    if let Err(e) = sqlite::insert_rejected_url(&db_conn.lock().unwrap(), &canonical::canonicalize(config, url), &canonical::canonicalize(config, referrer_url), reason, detail) {
+        debug_log(config.debug, &format!("Failed to log rejected URL {} in SQLite: {}", url, e));
+    }
+}
+
 // Save some recursion, remove duplicates and links we've seen.
-pub(crate) fn filter_links(config: &config::Config, links: Vec<String>, seen: &Arc<Mutex<HashSet<String>>>, db_conn: &Arc<Mutex<Connection>>, referrer_url: &String) -> HashSet<Url> {
+// Only links to pages are checked for spider traps, so images don't use up the per-path budgets.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn filter_links(config: &config::Config, client: &Client, links: Vec<String>, seen: &Arc<Mutex<HashSet<String>>>, db_conn: &Arc<Mutex<Connection>>, base_url: &Url, filters: &LinkFilters, seed: Option<&Url>, check_traps: bool) -> HashSet<Url> {
+    let referrer_url = &base_url.to_string();
     let mut links_set: HashSet<Url> = HashSet::new();
-    links_set.extend(links.into_iter().filter_map(|mut link: String| {
-        // Handle any links that are relative paths
-        link = match http::handle_relative_paths(config, &link, referrer_url) {
-            Ok(value) => value,
-            Err(_) => {
-                return None;
-            },
+    for link in links {
+        // Resolve any relative links against the page they were found on
+        let link = match http::resolve_url(config, &link, base_url) {
+            Some(link) => link,
+            None => continue,
         };
-        
+
         // Check if the link is valid
-        let (link_url, is_valid) = is_valid_site(config, &link);
-        if is_valid {
-            if let Some(link_url) = link_url {
-                let formatted_link_url = format_url_for_storage(link_url.to_string());
-                if seen.lock().unwrap().contains(&formatted_link_url) {
-                    // Check if we have already seen this URL
-                    debug_log(config.debug, &format!("Ignoring previously seen URL: {}", formatted_link_url));
-                    return None;
-                } else if sqlite::is_previously_completed_url(&mut db_conn.lock().unwrap(), &formatted_link_url).unwrap().unwrap() {
-                    // Check if this URL has already been completed
-                    seen.lock().unwrap().insert(formatted_link_url.clone());
-                    debug_log(config.debug, &format!("Ignoring completed URL: {}", formatted_link_url));
-                    return None;
-                } else if config.respect_robots && is_robots_txt_blocked(config, db_conn, link_url.clone(), &referrer_url) {
-                    // Check if this URL should be ignored due to robots.txt
-                    seen.lock().unwrap().insert(formatted_link_url.clone());
-                    debug_log(config.debug, &format!("Ignoring robots.txt blocked URL: {}", link_url));
-                    return None;
-                }
-                seen.lock().unwrap().insert(formatted_link_url.clone());
-                return Some(link_url);
+        let link_url = match is_valid_site(config, &filters.domains, link.as_str(), seed) {
+            (Some(link_url), true) => link_url,
+            _ => continue,
+        };
+        let formatted_link_url = canonical::canonicalize(config, link_url.as_str());
+        let previously_seen = !seen.lock().unwrap().insert(formatted_link_url.clone());
+        if previously_seen {
+            // Check if we have already seen this URL
+            debug_log(config.debug, &format!("Ignoring previously seen URL: {}", formatted_link_url));
+            continue;
+        }
+        if let Err(rule) = filters.rules.check(&link_url) {
+            // Check if a URL rule rejects this URL
+            debug_log(config.debug, &format!("Ignoring URL rejected by rule [{}]: {}", rule, link_url));
+            log_rejected_url(config, db_conn, link_url.as_str(), referrer_url, "url_rule", &rule);
+            continue;
+        }
+        let previously_completed = sqlite::is_previously_completed_url(&db_conn.lock().unwrap(), &formatted_link_url).unwrap().unwrap();
+        if previously_completed {
+            // Check if this URL has already been completed
+            debug_log(config.debug, &format!("Ignoring completed URL: {}", formatted_link_url));
+            continue;
+        }
+        if config.respect_robots {
+            let robots_txt = robots::get_robots_txt(config, client, db_conn, &link_url).await;
+            if robots_txt == robots::RobotsTxt::Unreachable {
+                // The host's robots.txt can't be fetched right now, so leave this URL for a later page to find again.
+                debug_log(config.debug, &format!("Ignoring URL while its robots.txt is unreachable: {}", link_url));
+                log_rejected_url(config, db_conn, link_url.as_str(), referrer_url, "robots_unreachable", &"robots.txt is unreachable".to_string());
+                seen.lock().unwrap().remove(&formatted_link_url);
+                continue;
             }
+            if is_robots_txt_blocked(config, db_conn, &robots_txt, link_url.clone(), referrer_url) {
+                // Check if this URL should be ignored due to robots.txt
+                debug_log(config.debug, &format!("Ignoring robots.txt blocked URL: {}", link_url));
+                continue;
+            }
+        }
+        // The trap check comes last, so only URLs that pass everything else count against the trap budgets.
+        // A URL held back while its robots.txt is unreachable can be found again without being counted twice.
+        let trap = if check_traps { filters.traps.check(&link_url) } else { Ok(()) };
+        if let Err((reason, detail)) = trap {
+            // Check if this URL looks like a spider trap
+            debug_log(config.debug, &format!("Ignoring likely spider trap {}: {}", link_url, detail));
+            log_rejected_url(config, db_conn, link_url.as_str(), referrer_url, reason.as_str(), &detail);
+            continue;
         }
-        None
-    }));
+        links_set.insert(link_url);
+    }
     links_set
 }
 
-pub(crate) fn filter_links_to_urls(config: &config::Config, links: data::SiteLinks, seen: &Arc<Mutex<HashSet<String>>>, db_conn: &Arc<Mutex<Connection>>, referrer_url: &String) -> data::SiteUrls {
-    let link_links_set = filter_links(config, links.link_links, seen, db_conn, referrer_url);
-    let img_links_set = filter_links(config, links.img_links, seen, db_conn, referrer_url);
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn filter_links_to_urls(config: &config::Config, client: &Client, links: data::SiteLinks, seen: &Arc<Mutex<HashSet<String>>>, db_conn: &Arc<Mutex<Connection>>, base_url: &Url, limits: &frontier::CrawlLimits, filters: &LinkFilters, entry: &data::FrontierEntry) -> data::SiteUrls {
+    // Links on this page are one level deeper than it, so don't follow them past MAX_DEPTH.
+    // In list mode only the seeds are crawled, so no links are followed at all.
+    let link_links = if config.list_mode {
+        Vec::new()
+    } else if limits.within_depth(entry.depth + 1) {
+        links.link_links
+    } else {
+        debug_log(config.debug, &format!("Not following links from {}, they are past the max depth", base_url));
+        Vec::new()
+    };
+    let link_links_set = filter_links(config, client, link_links, seen, db_conn, base_url, filters, entry.seed.as_ref(), true).await;
+    let img_links_set = filter_links(config, client, links.img_links, seen, db_conn, base_url, filters, entry.seed.as_ref(), false).await;
     // Convert the HashSet to a Vec, preventing duplicates.
-    let link_urls_vec: Vec<Url> = link_links_set.into_iter().collect();
+    // Only keep the links that fit in their domain's page budget.
+    let link_urls_vec: Vec<Url> = link_links_set.into_iter().filter(|url| {
+        if limits.try_claim(url) {
+            return true;
+        }
+        debug_log(config.debug, &format!("Ignoring URL over its domain page budget: {}", url));
+        false
+    }).collect();
     let img_urls_vec: Vec<Url> = img_links_set.into_iter().collect();
     data::SiteUrls {
         link_urls: link_urls_vec,
@@ -165,20 +299,19 @@ pub(crate) fn filter_links_to_urls(config: &config::Config, links: data::SiteLin
 }
 
 // Determine if a site or relative path is valid.
-pub(crate) fn is_valid_site(config: &config::Config, url: &str) -> (Option<Url>, bool) {
-    if let Ok(parsed_url) = Url::parse(&url) {
-        // Check if the domain of the URL is in the list of permitted domains.
-        if let Some(domain) = parsed_url.domain() {
-            if (config.free_crawl || config.permitted_domains.iter().any(|d| domain.eq(d)))
-                  && !config.blacklist_domains.iter().any(|d| domain.eq(d)) {
+pub(crate) fn is_valid_site(config: &config::Config, domains: &domains::DomainRules, url: &str, seed: Option<&Url>) -> (Option<Url>, bool) {
+    if let Ok(parsed_url) = Url::parse(url) {
+        // Check if the host of the URL is in the list of permitted domains.
+        if let Some(host) = parsed_url.host_str() {
+            if domains.is_allowed(&parsed_url, seed) {
                 return (Some(parsed_url), true);
             } else {
-                // If the domain isn't in the list of permitted domains..
-                debug_log(config.debug, &format!("Domain {} isn't in the list of permitted domains: {:?}", domain, parsed_url));
+                // If the host isn't in the list of permitted domains, or is blacklisted..
+                debug_log(config.debug, &format!("Domain {} isn't in the list of permitted domains: {:?}", host, parsed_url));
                 return (Some(parsed_url), false);
             }
         } else {
-            // If the URL doesn't have a domain..
+            // If the URL doesn't have a host..
             debug_log(config.debug, &format!("URL {} doesn't have a domain", url));
             return (Some(parsed_url), false);
         }
@@ -186,13 +319,6 @@ pub(crate) fn is_valid_site(config: &config::Config, url: &str) -> (Option<Url>,
     (None, false)
 }
 
-// Run this before storing a url. Prevent storing the same url multiple times.
-pub(crate) fn format_url_for_storage(url: String) -> String{
-    // capture everything after the protocol and before the query
-    let re = Regex::new(r"^https?://(www\.)?([^?]*).*").unwrap();
-    let formatted_url = re.replace(&url, "$2").trim_end_matches('/').to_string();
-    formatted_url
-}
 
 // Get the config path from the command line arguments
 pub(crate) fn get_config_path() -> String {
@@ -200,53 +326,162 @@ pub(crate) fn get_config_path() -> String {
     let default_config_path = "".to_string();
     let config_path = args.iter().position(|x| x == "-c")
     .and_then(|index| args.get(index + 1))
-    .unwrap_or_else(|| &default_config_path)
+    .unwrap_or(&default_config_path)
     .to_string();
     config_path
 }
 
-// A simple in-memory cache that uses a HashMap and a Mutex.
-// This is used to cache robots.txt files for each run. 
-struct InMemoryCache {
-    map: Mutex<HashMap<String, String>>,
+// Check if the user asked to resume an interrupted crawl
+pub(crate) fn get_resume_flag() -> bool {
+    env::args().any(|x| x == "--resume")
+}
+
+// Get the seeds file from the command line, - to read seeds from stdin
+pub(crate) fn get_seeds_path() -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    args.iter().position(|x| x == "--seeds")
+        .and_then(|index| args.get(index + 1))
+        .map(|path| path.to_string())
+}
+
+// Check if the user asked to only fetch the seeds, without following their links
+pub(crate) fn get_list_mode_flag() -> bool {
+    env::args().any(|x| x == "--list")
 }
-impl InMemoryCache {
-    fn new() -> InMemoryCache {
-        InMemoryCache {
-            map: Mutex::new(HashMap::new()),
+
+// Get the URLs to start crawling from: SEEDS, then the URLs in SEEDS_FILE, or stdin if it's -.
+// Falls back to STARTING_URL if neither gives any seeds.
+pub(crate) fn get_seeds(config: &config::Config) -> Vec<Url> {
+    let mut lines = config.seeds.clone();
+    if !config.seeds_file.is_empty() {
+        let contents = if config.seeds_file == "-" {
+            let mut contents = String::new();
+            std::io::stdin().read_to_string(&mut contents).map(|_| contents)
+        } else {
+            std::fs::read_to_string(&config.seeds_file)
+        };
+        match contents {
+            Ok(contents) => lines.extend(parse_seed_list(&config.seeds_file, &contents)),
+            Err(e) => eprintln!("Failed to read seeds file {}: {}", config.seeds_file, e),
         }
     }
-    fn get(&self, key: &str) -> Option<String> {
-        let map = self.map.lock().unwrap();
-        map.get(key).cloned()
+
+    let mut seeds: Vec<Url> = Vec::new();
+    let mut unique: HashSet<String> = HashSet::new();
+    for line in lines.iter() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match Url::parse(line) {
+            Ok(seed) => {
+                if unique.insert(seed.to_string()) {
+                    seeds.push(seed);
+                }
+            }
+            Err(e) => debug_log(config.debug, &format!("Ignoring invalid seed {}: {}", line, e)),
+        }
     }
-    fn set(&self, key: String, value: String) {
-        let mut map = self.map.lock().unwrap();
-        map.insert(key, value);
+    if seeds.is_empty() {
+        if let Ok(seed) = Url::parse(&config.starting_url) {
+            seeds.push(seed);
+        }
     }
+    seeds
 }
-lazy_static! {
-    static ref INMEMORY_CACHE: InMemoryCache = InMemoryCache::new();
+
+// The formats a seeds file can be in.
+#[derive(Debug, PartialEq)]
+enum SeedFormat {
+    // One URL per line.
+    Text,
+    // CSV or TSV, split on the given delimiter.
+    Csv(char),
+    // A sitemap's <loc> entries.
+    Sitemap,
 }
 
+// Work out a seeds file's format from its extension: .csv, .tsv, .xml or .txt.
+// For stdin and other extensions, look at the first line: XML is a sitemap, and a header row that isn't a URL but has tabs or commas is a TSV or CSV.
+fn seed_format(path: &str, contents: &str) -> SeedFormat {
+    let path = path.to_lowercase();
+    if path.ends_with(".csv") {
+        return SeedFormat::Csv(',');
+    } else if path.ends_with(".tsv") {
+        return SeedFormat::Csv('\t');
+    } else if path.ends_with(".xml") {
+        return SeedFormat::Sitemap;
+    } else if path.ends_with(".txt") {
+        return SeedFormat::Text;
+    }
+    let first_line = contents.lines().map(|line| line.trim()).find(|line| !line.is_empty() && !line.starts_with('#')).unwrap_or("");
+    if first_line.starts_with('<') {
+        SeedFormat::Sitemap
+    } else if Url::parse(first_line).is_ok() {
+        SeedFormat::Text
+    } else if first_line.contains('\t') {
+        SeedFormat::Csv('\t')
+    } else if first_line.contains(',') {
+        SeedFormat::Csv(',')
+    } else {
+        SeedFormat::Text
+    }
+}
 
+// Pull the seed URLs out of a seeds file, in the format picked by seed_format. The file can be:
+// - Plain text, one URL per line. Blank lines and lines starting with # are skipped. URLs are kept whole, even with commas in them.
+// - CSV or TSV, where the first field on each row that's an http or https URL is used, so header rows are skipped.
+//   Quoted fields can hold the delimiter.
+// - A sitemap, where every <loc> is used.
+pub(crate) fn parse_seed_list(path: &str, contents: &str) -> Vec<String> {
+    let delimiter = match seed_format(path, contents) {
+        SeedFormat::Sitemap => {
+            let re = Regex::new(r"(?s)<loc>\s*(.*?)\s*</loc>").unwrap();
+            return re.captures_iter(contents)
+                .map(|cap| cap[1].replace("&amp;", "&"))
+                .collect();
+        }
+        SeedFormat::Text => {
+            return contents.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string())
+                .collect();
+        }
+        SeedFormat::Csv(delimiter) => delimiter,
+    };
 
-// Apparently pulling this in is a common practice. I didnt write it.
-// Using this similar to defer in Go, we can ensure that a function is run when the current scope is exited.
-pub(crate) struct Defer<F: FnOnce()> {
-    f: Option<F>,
-}
-impl<F: FnOnce()> Defer<F> {
-    pub(crate) fn new(f: F) -> Defer<F> {
-        Defer { f: Some(f) }
+    let mut seeds = Vec::new();
+    for line in contents.lines() {
+        let field = split_csv_row(line, delimiter).into_iter()
+            .map(|field| field.trim().to_string())
+            .find(|field| Url::parse(field).is_ok_and(|url| url.scheme() == "http" || url.scheme() == "https"));
+        if let Some(field) = field {
+            seeds.push(field);
+        }
     }
+    seeds
 }
-impl<F: FnOnce()> Drop for Defer<F> {
-    fn drop(&mut self) {
-        if let Some(f) = self.f.take() {
-            f();
+
+// Split a CSV or TSV row into its fields, keeping delimiters inside double quotes and unescaping "" to ".
+fn split_csv_row(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
         }
     }
+    fields.push(field);
+    fields
 }
 
 #[cfg(test)]
@@ -277,11 +512,166 @@ mod tests {
         assert_eq!(site_urls.link_links, vec!["https://www.cnn.com"]);
     }
 
+    #[test]
+    fn test_extract_feed_links() {
+        let html = "<html><head>
+            <link rel='alternate' type='application/rss+xml' href='/rss.xml'>
+            <link rel='alternate' type='Application/Atom+XML' href='https://www.example.com/atom'>
+            <link rel='alternate' hreflang='fr' href='/fr/'>
+            </head><body><a href='/news/feed/'>Feed</a><a href='/rss.xml'>RSS</a><a href='/feeds.html'>Feeds</a></body></html>";
+        let site_links = extract_links(&Html::parse_document(html)).unwrap();
+        assert_eq!(site_links.feed_links, vec!["/rss.xml", "https://www.example.com/atom", "/news/feed/"]);
+    }
+
+    #[test]
+    fn test_extract_nofollow_links_and_meta_tags() {
+        let html = "<html><head>
+            <meta name='ROBOTS' content='noindex'><meta name='description' content='A page'><meta charset='utf-8'>
+            </head><body>
+            <a href='/ad' rel='sponsored noopener'>Ad</a><a href='/comment' rel='UGC'>Comment</a>
+            <a href='/both' rel='nofollow'>Both</a><a href='/both'>Both</a><a href='/plain' rel='noopener'>Plain</a>
+            </body></html>";
+        let site_links = extract_links(&Html::parse_document(html)).unwrap();
+        assert_eq!(site_links.link_links, vec!["/ad", "/comment", "/both", "/both", "/plain"]);
+        assert_eq!(site_links.nofollow_links, vec!["/ad", "/comment"]);
+        assert_eq!(site_links.meta_tags, vec![("ROBOTS".to_string(), "noindex".to_string()), ("description".to_string(), "A page".to_string())]);
+    }
+
+    // Extract the links from a fixture page, and resolve them the way the crawler would.
+    fn resolve_fixture_links(html: &str, page_url: &str) -> (Vec<String>, Vec<String>) {
+        let config: config::Config = config::Config::new("crab.json".to_string());
+        let site_links = extract_links(&parse_html(html).unwrap()).unwrap();
+        let base_url = resolve_base_url(site_links.base_href.as_deref(), &Url::parse(page_url).unwrap());
+        let resolve = |links: Vec<String>| links.iter()
+            .filter_map(|link| http::resolve_url(&config, link, &base_url))
+            .map(|url| url.to_string())
+            .collect::<Vec<String>>();
+        (resolve(site_links.link_links), resolve(site_links.img_links))
+    }
+
+    #[test]
+    fn test_base_href_absolute() {
+        let html = include_str!("../tests/fixtures/base_absolute.html");
+        let (links, images) = resolve_fixture_links(html, "https://www.example.com/guide/index.html");
+        assert_eq!(links, vec![
+            "https://static.example.com/docs/v2/intro.html",
+            "https://static.example.com/docs/v1/intro.html",
+            "https://static.example.com/about",
+            "https://www.example.org/elsewhere",
+        ]);
+        assert_eq!(images, vec!["https://static.example.com/docs/v2/images/logo.png"]);
+    }
+
+    #[test]
+    fn test_extract_canonical_href() {
+        let site_links = extract_links(&parse_html(include_str!("../tests/fixtures/base_absolute.html")).unwrap()).unwrap();
+        assert_eq!(site_links.canonical_href.as_deref(), Some("index.html"));
+        let site_links = extract_links(&parse_html(include_str!("../tests/fixtures/no_base.html")).unwrap()).unwrap();
+        assert_eq!(site_links.canonical_href, None);
+    }
+
+    #[test]
+    fn test_base_href_relative_uses_first_href() {
+        let html = include_str!("../tests/fixtures/base_relative.html");
+        let (links, _) = resolve_fixture_links(html, "https://www.example.com/guide/index.html");
+        assert_eq!(links, vec![
+            "https://www.example.com/mirror/page.html",
+            "https://www.example.com/mirror/?sort=asc",
+        ]);
+    }
+
+    #[test]
+    fn test_no_base_href_uses_page_url() {
+        let html = include_str!("../tests/fixtures/no_base.html");
+        let (links, images) = resolve_fixture_links(html, "https://www.example.com/guide/index.html");
+        assert_eq!(links, vec!["https://www.example.com/guide/page.html"]);
+        assert_eq!(images, vec!["https://www.example.com/images/photo.jpg"]);
+    }
+
+    #[test]
+    fn test_resolve_base_url_ignores_unusable_hrefs() {
+        let page_url = Url::parse("https://www.example.com/guide/").unwrap();
+        assert_eq!(resolve_base_url(None, &page_url), page_url);
+        assert_eq!(resolve_base_url(Some("javascript:void(0)"), &page_url), page_url);
+        assert_eq!(resolve_base_url(Some("http://[::1"), &page_url), page_url);
+    }
+
+    #[test]
+    fn test_parse_crawl_delay() {
+        let robots_txt = "User-agent: *\nCrawl-delay: 5\nDisallow: /private\n\nUser-agent: Crab-Crawler/1.0\nCrawl-delay: 2.5\n";
+        assert_eq!(parse_crawl_delay(robots_txt, "crab-crawler"), Some(Duration::from_millis(2500)));
+
+        let robots_txt = "User-agent: Googlebot\nCrawl-delay: 1\n\nUser-agent: *\nCrawl-delay: 10 # be nice\n";
+        assert_eq!(parse_crawl_delay(robots_txt, "crab-crawler"), Some(Duration::from_secs(10)));
+
+        // A group for a shorter token isn't ours.
+        let robots_txt = "User-agent: crab\nCrawl-delay: 1\n\nUser-agent: *\nCrawl-delay: 3\n";
+        assert_eq!(parse_crawl_delay(robots_txt, "crab-crawler"), Some(Duration::from_secs(3)));
+
+        let robots_txt = "User-agent: *\nDisallow: /\n";
+        assert_eq!(parse_crawl_delay(robots_txt, "crab-crawler"), None);
+    }
+
+    #[test]
+    fn test_is_robots_txt_blocked_matches_robots_user_agent() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.sqlite_enabled = false;
+        config.robots_user_agent = "crab-crawler".to_string();
+        let db_conn = Arc::new(Mutex::new(sqlite::connect_sqlite_and_migrate(&config).unwrap().unwrap()));
+        let is_blocked = |robots_txt: &str, url: &str| {
+            let robots_txt = robots::RobotsTxt::Rules(robots_txt.to_string());
+            is_robots_txt_blocked(&config, &db_conn, &robots_txt, Url::parse(url).unwrap(), "https://www.example.com/")
+        };
+
+        // Only the * group applies when we aren't named.
+        let robots_txt = "User-agent: *\nDisallow: /private\n\nUser-agent: Googlebot\nDisallow: /\n";
+        assert!(is_blocked(robots_txt, "https://www.example.com/private/page"));
+        assert!(!is_blocked(robots_txt, "https://www.example.com/public"));
+
+        // A group that names us replaces the * group.
+        let robots_txt = "User-agent: *\nDisallow: /\n\nUser-agent: Crab-Crawler\nDisallow: /private\n";
+        assert!(!is_blocked(robots_txt, "https://www.example.com/public"));
+        assert!(is_blocked(robots_txt, "https://www.example.com/private/page"));
+
+        // The browser user agents we send aren't used for matching.
+        let robots_txt = "User-agent: *\nAllow: /\n\nUser-agent: Mozilla\nDisallow: /\n";
+        assert!(!is_blocked(robots_txt, "https://www.example.com/public"));
+    }
+
+    #[test]
+    fn test_get_seeds() {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.starting_url = "https://www.start.com".to_string();
+        assert_eq!(get_seeds(&config), vec![Url::parse("https://www.start.com").unwrap()]);
+
+        config.seeds = vec!["https://one.example.com/".to_string(), "not a url".to_string()];
+        config.seeds_file = "tests/fixtures/seeds.txt".to_string();
+        let seeds: Vec<String> = get_seeds(&config).iter().map(|seed| seed.to_string()).collect();
+        assert_eq!(seeds, vec!["https://one.example.com/", "https://two.example.com/news", "http://127.0.0.1:8765/"]);
+    }
+
+    #[test]
+    fn test_parse_seed_list() {
+        let text = "# seeds, one per line\nhttps://a.example.com/\n\nhttps://b.example.com/x,y\n";
+        assert_eq!(parse_seed_list("seeds.txt", text), vec!["https://a.example.com/", "https://b.example.com/x,y"]);
+        assert_eq!(parse_seed_list("-", text), vec!["https://a.example.com/", "https://b.example.com/x,y"]);
+
+        let csv = "id,url,title\n1,\"https://a.example.com/a,b\",Home\n2,https://b.example.com/news,\"News\"\n3,not a url,Broken\n";
+        let expected = vec!["https://a.example.com/a,b", "https://b.example.com/news"];
+        assert_eq!(parse_seed_list("seeds.csv", csv), expected);
+        assert_eq!(parse_seed_list("-", csv), expected);
+        assert_eq!(parse_seed_list("seeds.tsv", "url\ttitle\nhttps://a.example.com/x,y\tHome\n"), vec!["https://a.example.com/x,y"]);
+
+        let sitemap = "<?xml version=\"1.0\"?><urlset><url><loc> https://a.example.com/?a=1&amp;b=2 </loc></url><url><loc>https://b.example.com/</loc></url></urlset>";
+        assert_eq!(parse_seed_list("sitemap.xml", sitemap), vec!["https://a.example.com/?a=1&b=2", "https://b.example.com/"]);
+        assert_eq!(parse_seed_list("-", sitemap), vec!["https://a.example.com/?a=1&b=2", "https://b.example.com/"]);
+    }
+
     #[test]
     fn test_is_valid_site() {
         let url = "https://www.cnn.com";
         let config: config::Config = config::Config::new("crab.json".to_string());
-        let (_, is_valid) = is_valid_site(&config, url);
+        let (_, is_valid) = is_valid_site(&config, &domains::DomainRules::new(&config, &[]), url, None);
         assert!(is_valid);
     }
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+
+use crate::config;
+use crate::tools;
+
+lazy_static! {
+    static ref DIGITS: Regex = Regex::new(r"\d+").unwrap();
+}
+
+// Why a URL was rejected as a likely spider trap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TrapReason {
+    RepeatedSegment,
+    PathDepth,
+    UrlLength,
+    QueryVariants,
+    PatternBudget,
+}
+impl TrapReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrapReason::RepeatedSegment => "repeated_segment",
+            TrapReason::PathDepth => "path_depth",
+            TrapReason::UrlLength => "url_length",
+            TrapReason::QueryVariants => "query_variants",
+            TrapReason::PatternBudget => "pattern_budget",
+        }
+    }
+}
+
+// Spots URLs that look like spider traps, such as endless calendars, recursive paths and faceted search.
+// Every limit can be turned off with 0.
+pub(crate) struct TrapDetector {
+    max_repeated_segments: usize,
+    max_path_depth: usize,
+    max_url_length: usize,
+    max_query_variants: usize,
+    max_urls_per_pattern: usize,
+    pattern_budgets: Vec<(Regex, usize)>,
+    query_variants: Mutex<HashMap<String, usize>>,
+    pattern_counts: Mutex<HashMap<String, usize>>,
+}
+
+impl TrapDetector {
+    pub fn new(config: &config::Config) -> Self {
+        let pattern_budgets = config.trap_pattern_budgets.iter().filter_map(|(pattern, budget)| {
+            match Regex::new(pattern) {
+                Ok(re) => Some((re, *budget)),
+                Err(e) => {
+                    tools::debug_log(config.debug, &format!("Ignoring invalid trap pattern {}: {}", pattern, e));
+                    None
+                }
+            }
+        }).collect();
+        TrapDetector {
+            max_repeated_segments: config.trap_max_repeated_segments,
+            max_path_depth: config.trap_max_path_depth,
+            max_url_length: config.trap_max_url_length,
+            max_query_variants: config.trap_max_query_variants,
+            max_urls_per_pattern: config.trap_max_urls_per_pattern,
+            pattern_budgets,
+            query_variants: Mutex::new(HashMap::new()),
+            pattern_counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Check a newly discovered URL. Each URL should only be checked once, since this counts it against its path and pattern.
+    pub fn check(&self, url: &Url) -> Result<(), (TrapReason, String)> {
+        if self.max_url_length > 0 && url.as_str().len() > self.max_url_length {
+            return Err((TrapReason::UrlLength, format!("URL is {} characters, over the limit of {}", url.as_str().len(), self.max_url_length)));
+        }
+
+        let segments: Vec<&str> = url.path_segments().map(|segments| segments.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+        if self.max_path_depth > 0 && segments.len() > self.max_path_depth {
+            return Err((TrapReason::PathDepth, format!("path is {} segments deep, over the limit of {}", segments.len(), self.max_path_depth)));
+        }
+        if self.max_repeated_segments > 0 {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for segment in segments.iter() {
+                let count = counts.entry(segment).or_insert(0);
+                *count += 1;
+                if *count > self.max_repeated_segments {
+                    return Err((TrapReason::RepeatedSegment, format!("segment {} appears {} times in the path", segment, count)));
+                }
+            }
+        }
+
+        let host = url.host_str().unwrap_or("");
+        if self.max_query_variants > 0 && url.query().is_some() {
+            let path = format!("{}{}", host, url.path());
+            let mut query_variants = self.query_variants.lock().unwrap();
+            let variants = query_variants.entry(path.clone()).or_insert(0);
+            if *variants >= self.max_query_variants {
+                return Err((TrapReason::QueryVariants, format!("{} already has {} query variants", path, variants)));
+            }
+            *variants += 1;
+        }
+
+        // Count the URL against the first configured pattern it matches, and against its generalised shape.
+        let mut pattern_counts = self.pattern_counts.lock().unwrap();
+        let mut budgets = Vec::new();
+        if let Some((re, budget)) = self.pattern_budgets.iter().find(|(re, _)| re.is_match(url.path())) {
+            budgets.push((format!("{}{}", host, re.as_str()), *budget));
+        }
+        if self.max_urls_per_pattern > 0 {
+            budgets.push((format!("{}{}", host, path_pattern(url.path())), self.max_urls_per_pattern));
+        }
+        for (pattern, budget) in budgets.iter() {
+            if pattern_counts.get(pattern).copied().unwrap_or(0) >= *budget {
+                return Err((TrapReason::PatternBudget, format!("pattern {} has used its budget of {} URLs", pattern, budget)));
+            }
+        }
+        for (pattern, _) in budgets {
+            *pattern_counts.entry(pattern).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+}
+
+// Generalise a path by replacing every run of digits, so /calendar/2024/05 and /calendar/1999/12 share the pattern /calendar/{n}/{n}.
+fn path_pattern(path: &str) -> String {
+    DIGITS.replace_all(path, "{n}").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> TrapDetector {
+        let mut config = config::Config::new("crab.json".to_string());
+        config.trap_max_repeated_segments = 2;
+        config.trap_max_path_depth = 6;
+        config.trap_max_url_length = 100;
+        config.trap_max_query_variants = 3;
+        config.trap_max_urls_per_pattern = 5;
+        config.trap_pattern_budgets = HashMap::from([("^/search".to_string(), 2)]);
+        TrapDetector::new(&config)
+    }
+
+    fn reason(detector: &TrapDetector, url: &str) -> Option<TrapReason> {
+        detector.check(&Url::parse(url).unwrap()).err().map(|(reason, _)| reason)
+    }
+
+    #[test]
+    fn test_path_shape_traps() {
+        let detector = detector();
+        assert_eq!(reason(&detector, "https://www.example.com/a/b/c"), None);
+        assert_eq!(reason(&detector, "https://www.example.com/a/b/a/b/a/b"), Some(TrapReason::RepeatedSegment));
+        assert_eq!(reason(&detector, "https://www.example.com/1/2/3/4/5/6/7"), Some(TrapReason::PathDepth));
+        assert_eq!(reason(&detector, &format!("https://www.example.com/{}", "x".repeat(100))), Some(TrapReason::UrlLength));
+    }
+
+    #[test]
+    fn test_query_variants() {
+        let detector = detector();
+        for page in 1..=3 {
+            assert_eq!(reason(&detector, &format!("https://www.example.com/list?color=red&page={}", page)), None);
+        }
+        assert_eq!(reason(&detector, "https://www.example.com/list?color=blue"), Some(TrapReason::QueryVariants));
+        assert_eq!(reason(&detector, "https://www.example.com/other?color=blue"), None);
+    }
+
+    #[test]
+    fn test_pattern_budgets() {
+        let detector = detector();
+        for day in 1..=5 {
+            assert_eq!(reason(&detector, &format!("https://www.example.com/calendar/2024/05/{}", day)), None);
+        }
+        assert_eq!(reason(&detector, "https://www.example.com/calendar/1999/12/31"), Some(TrapReason::PatternBudget));
+        assert_eq!(reason(&detector, "https://www.other.com/calendar/1999/12/31"), None);
+
+        assert_eq!(reason(&detector, "https://www.example.com/search/red"), None);
+        assert_eq!(reason(&detector, "https://www.example.com/search/blue"), None);
+        assert_eq!(reason(&detector, "https://www.example.com/search/green"), Some(TrapReason::PatternBudget));
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        assert_eq!(path_pattern("/calendar/2024/05/12"), "/calendar/{n}/{n}/{n}");
+        assert_eq!(path_pattern("/post/my-post-123"), "/post/my-post-{n}");
+    }
+}